@@ -0,0 +1,91 @@
+use std::{collections::HashMap, hint::black_box, time::Instant};
+
+use nandscript::{ChipEvaluator, Executable, parse};
+
+const LIBRARY: &str = "
+AND
+out = NAND(NAND(a: a, b: b), NAND(a: a, b: b))
+
+OR
+out = NAND(NAND(a: a, b: a), NAND(a: b, b: b))
+
+XOR
+out = NAND(NAND(a: a, NAND(a: a, b: b)), NAND(NAND(a: a, b: b), b: b))
+
+FULL
+INPUTS: a, b, c
+OUTPUTS: sum, carry
+sum = XOR(XOR(a: a, b: b), b: c)
+carry = OR(AND(a: a, b: b), AND(XOR(a: a, b: b), b: c))
+";
+
+const ITERATIONS: usize = 2000;
+
+// Carry into bit `bit` of a ripple carry adder, as a positional argument
+fn carry(bit: usize) -> String {
+    if bit == 0 {
+        return String::from("c: 0");
+    }
+    format!("FULL.carry(a: a{0}, b: b{0}, {1})", bit - 1, carry(bit - 1))
+}
+
+fn adder(bits: usize) -> String {
+    let mut source = String::new();
+    for bit in 0..bits {
+        source += &format!("s{0} = FULL.sum(a: a{0}, b: b{0}, {1})\n", bit, carry(bit));
+    }
+    source
+}
+
+// Small xorshift generator so runs are repeatable without pulling in a crate
+fn random_inputs(state: &mut u64, bits: usize) -> HashMap<String, u8> {
+    let mut inputs = HashMap::<String, u8>::new();
+    for bit in 0..bits {
+        for name in ["a", "b"] {
+            *state ^= *state << 13;
+            *state ^= *state >> 7;
+            *state ^= *state << 17;
+            inputs.insert(format!("{}{}", name, bit), *state as u8);
+        }
+    }
+    inputs
+}
+
+fn main() {
+    let bits = 8;
+    let mut cpu = ChipEvaluator::new();
+    cpu.load_library(LIBRARY).unwrap();
+    let code = parse(&adder(bits)).unwrap();
+    cpu.load_chip("ADD8", &code);
+    let netlist = cpu.flatten("ADD8").unwrap();
+
+    let mut state = 0x2545f4914f6cdd1d;
+    let vectors: Vec<HashMap<String, u8>> = (0..ITERATIONS).map(|_| random_inputs(&mut state, bits)).collect();
+
+    // Both paths have to agree before their timings mean anything
+    for inputs in &vectors {
+        let walked = cpu.eval(code.clone(), inputs).unwrap();
+        let compiled = netlist.eval(inputs);
+        for bit in 0..bits {
+            let name = format!("s{}", bit);
+            assert_eq!(walked.get(&name), compiled.get(&name), "{} differs for {:?}", name, inputs);
+        }
+    }
+
+    let start = Instant::now();
+    for inputs in &vectors {
+        black_box(cpu.eval(code.clone(), black_box(inputs)).unwrap());
+    }
+    let walked = start.elapsed();
+
+    let start = Instant::now();
+    for inputs in &vectors {
+        black_box(netlist.eval(black_box(inputs)));
+    }
+    let compiled = start.elapsed();
+
+    println!("{}-bit adder, {} gates, {} vectors of 8 lanes", bits, netlist.gates.len(), ITERATIONS);
+    println!("tree walk: {:?} ({:?} per vector)", walked, walked / ITERATIONS as u32);
+    println!("netlist:   {:?} ({:?} per vector)", compiled, compiled / ITERATIONS as u32);
+    println!("speedup:   {:.1}x", walked.as_secs_f64() / compiled.as_secs_f64());
+}
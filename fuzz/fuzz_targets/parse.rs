@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nandscript::{parse, parse_library};
+
+// Malformed source must come back as an Err, never a panic
+fuzz_target!(|data: &[u8]| {
+    if let Ok(source) = std::str::from_utf8(data) {
+        let _ = parse(source);
+        let _ = parse_library(source);
+    }
+});
@@ -0,0 +1,425 @@
+use std::{collections::{HashMap, HashSet}, fmt};
+
+use crate::{ChipEvaluator, EvalError, Executable, Token, default_output};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TruthRow {
+    pub inputs: Vec<bool>,
+    pub outputs: Vec<bool>,
+}
+
+// Every combination of the swept inputs, the first input being the most significant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TruthTable {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub rows: Vec<TruthRow>,
+    // Inputs no output depends on. When collapsed, their rows only appear once, held at 0
+    pub dont_cares: Vec<bool>,
+}
+
+// The code of a chip and the inputs swept
+pub(crate) type TableKey = (Vec<Token>, Vec<String>);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TableOptions {
+    pub dont_cares: bool, // Mark inputs without effect as X and drop the rows they duplicate
+}
+
+impl TruthTable {
+    // Whether toggling the input never changes any output
+    pub fn is_dont_care(&self, input: usize) -> bool {
+        // Already collapsed, the full table is gone
+        if self.rows.len() != 1 << self.inputs.len() {
+            return self.dont_cares[input];
+        }
+        let bit = 1 << (self.inputs.len() - 1 - input);
+        (0..self.rows.len()).all(|row| self.rows[row].outputs == self.rows[row ^ bit].outputs)
+    }
+
+    pub fn collapse_dont_cares(&self) -> TruthTable {
+        let dont_cares: Vec<bool> = (0..self.inputs.len()).map(|i| self.is_dont_care(i)).collect();
+        let rows = self.rows.iter().filter(|row| {
+            row.inputs.iter().zip(&dont_cares).all(|(value, dont_care)| !dont_care || !value)
+        }).cloned().collect();
+        TruthTable { inputs: self.inputs.clone(), outputs: self.outputs.clone(), rows, dont_cares }
+    }
+}
+
+impl fmt::Display for TruthTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} | {}", self.inputs.join(" "), self.outputs.join(" "))?;
+        for row in &self.rows {
+            let cell = |(name, value): (&String, &bool)| format!("{:>width$}", *value as u8, width = name.len());
+            let inputs: Vec<String> = self.inputs.iter().zip(&row.inputs).zip(&self.dont_cares).map(|(cell_value, dont_care)| {
+                if *dont_care { format!("{:>width$}", "X", width = cell_value.0.len()) } else { cell(cell_value) }
+            }).collect();
+            let outputs: Vec<String> = self.outputs.iter().zip(&row.outputs).map(cell).collect();
+            writeln!(f, "{} | {}", inputs.join(" "), outputs.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl ChipEvaluator {
+    pub fn truth_table(&self, chip_name: &str, input_names: &[&str]) -> Result<TruthTable, EvalError> {
+        self.truth_table_with(chip_name, input_names, TableOptions::default())
+    }
+
+    pub fn truth_table_with(&self, chip_name: &str, input_names: &[&str], options: TableOptions) -> Result<TruthTable, EvalError> {
+        self.sweep(chip_name, input_names, &HashMap::new(), options)
+    }
+
+    // Sweeps only some of the inputs. Every other input is held at the lowest lane of its value in
+    // `fixed` in all rows, and at 0 when it is not given.
+    pub fn truth_table_partial(&self, chip_name: &str, sweep: &[&str], fixed: &HashMap<String, u8>) -> Result<TruthTable, EvalError> {
+        let fixed = fixed.iter().map(|(name, value)| (name.clone(), if value & 1 == 1 { self.mask() } else { 0 })).collect();
+        self.sweep(chip_name, sweep, &fixed, TableOptions::default())
+    }
+
+    // Whether truth_table would reuse a table, computed for this chip or one with the same code
+    pub fn is_table_cached(&self, chip_name: &str, input_names: &[&str]) -> bool {
+        self.table_key(chip_name, input_names).is_some_and(|key| self.table_cache.lock().unwrap().contains_key(&key))
+    }
+
+    // Docs and annotations leave the function alone, chips differing only in those share a table
+    fn table_key(&self, chip_name: &str, input_names: &[&str]) -> Option<TableKey> {
+        let code = self.chips.get(chip_name)?.iter().filter(|tok| !matches!(tok, Token::Doc(_) | Token::Attribute(_, _))).cloned().collect();
+        Some((code, input_names.iter().map(|name| name.to_string()).collect()))
+    }
+
+    // Tables with every other input at 0 are cached
+    fn sweep(&self, chip_name: &str, input_names: &[&str], fixed: &HashMap<String, u8>, options: TableOptions) -> Result<TruthTable, EvalError> {
+        let key = self.table_key(chip_name, input_names).ok_or_else(|| EvalError::UnknownChip(chip_name.to_string()))?;
+        // A chip sharing another's table adds its own dependencies, so reloading any of them drops the table
+        let cached = if fixed.is_empty() {
+            self.table_cache.lock().unwrap().get_mut(&key).map(|(table, used)| {
+                used.extend(self.dependencies(chip_name));
+                table.clone()
+            })
+        }
+        else {
+            None
+        };
+        let table = match cached {
+            Some(table) => table,
+            None => {
+                let table = self.sweep_rows(chip_name, input_names, fixed)?;
+                if fixed.is_empty() {
+                    self.table_cache.lock().unwrap().insert(key, (table.clone(), self.dependencies(chip_name)));
+                }
+                table
+            },
+        };
+        if options.dont_cares {
+            return Ok(table.collapse_dont_cares());
+        }
+        Ok(table)
+    }
+
+    fn sweep_rows(&self, chip_name: &str, input_names: &[&str], fixed: &HashMap<String, u8>) -> Result<TruthTable, EvalError> {
+        let code = self.chips.get(chip_name).ok_or_else(|| EvalError::UnknownChip(chip_name.to_string()))?;
+        // Intermediate locals are left out when the chip declares its outputs
+        let outputs = self.outputs_of(chip_name);
+        let row_count = 1usize << input_names.len();
+        let mut rows = Vec::<TruthRow>::with_capacity(row_count);
+
+        // Each bit lane within the width evaluates a different row. The lanes above it cannot be
+        // used, a literal 1 only fills the lanes within the width.
+        let width = self.width() as usize;
+        for base in (0..row_count).step_by(width) {
+            let lanes = (row_count - base).min(width);
+            let mut inputs = fixed.clone();
+            for (i, name) in input_names.iter().enumerate() {
+                let shift = input_names.len() - 1 - i;
+                let mut value = 0u8;
+                for lane in 0..lanes {
+                    if ((base + lane) >> shift) & 1 == 1 {
+                        value |= 1 << lane;
+                    }
+                }
+                inputs.insert(name.to_string(), value);
+            }
+
+            let result = self.eval(code.clone(), &inputs).map_err(|e| e.in_chip(chip_name))?;
+            for lane in 0..lanes {
+                let row = base + lane;
+                rows.push(TruthRow {
+                    inputs: (0..input_names.len()).map(|i| (row >> (input_names.len() - 1 - i)) & 1 == 1).collect(),
+                    outputs: outputs.iter().map(|name| (result.get(name).unwrap_or(&0) >> lane) & 1 == 1).collect(),
+                });
+            }
+        }
+
+        Ok(TruthTable {
+            inputs: input_names.iter().map(|x| x.to_string()).collect(),
+            outputs,
+            rows,
+            dont_cares: vec![false; input_names.len()],
+        })
+    }
+
+    // The default output of the chip for every combination of the inputs, packed with row r at
+    // bit r % 64 of word r / 64. Rows are numbered as in truth_table, the first input being the
+    // most significant, so six inputs fit in a single word.
+    pub fn packed_truth_table(&self, chip_name: &str, input_names: &[&str]) -> Result<Vec<u64>, EvalError> {
+        let code = self.chips.get(chip_name).ok_or_else(|| EvalError::UnknownChip(chip_name.to_string()))?;
+        let output = default_output(code);
+        let row_count = 1usize << input_names.len();
+        let mut words = vec![0u64; row_count.div_ceil(64)];
+
+        // The flattened netlist is much faster, but only for chips built from loaded chips alone
+        let netlist = self.flatten_for_eval(chip_name)?;
+
+        // Each bit lane within the width evaluates a different row, as in truth_table
+        let width = self.width() as usize;
+        for base in (0..row_count).step_by(width) {
+            let lanes = (row_count - base).min(width);
+            let mut inputs = HashMap::<String, u8>::new();
+            for (i, name) in input_names.iter().enumerate() {
+                let shift = input_names.len() - 1 - i;
+                let value = (0..lanes).filter(|lane| ((base + lane) >> shift) & 1 == 1).fold(0u8, |value, lane| value | 1 << lane);
+                inputs.insert(name.to_string(), value);
+            }
+
+            let result = match &netlist {
+                Some(netlist) => netlist.eval(&inputs),
+                None => self.eval(code.clone(), &inputs).map_err(|e| e.in_chip(chip_name))?,
+            };
+            // Widths that do not divide 64 put some rows of an eval in the next word
+            let value = *result.get(&output).unwrap_or(&0);
+            for lane in (0..lanes).filter(|lane| (value >> lane) & 1 == 1) {
+                let row = base + lane;
+                words[row / 64] |= 1 << (row % 64);
+            }
+        }
+        Ok(words)
+    }
+
+    // Node count of the reduced ordered binary decision diagram of the chip's default output, testing
+    // the inputs in the given order. Both terminals count, so constants have size 1. Chips computing
+    // the same function always have the same size, however many gates they are built from.
+    pub fn bdd_size(&self, chip_name: &str, input_order: &[&str]) -> Result<usize, EvalError> {
+        let words = self.packed_truth_table(chip_name, input_order)?;
+        let row_count = 1usize << input_order.len();
+
+        // Nodes 0 and 1 are the terminals. Built bottom up: the rows of a block only differ in the
+        // inputs after `level`, its first half having input `level` at 0.
+        let mut layer: Vec<usize> = (0..row_count).map(|row| ((words[row / 64] >> (row % 64)) & 1) as usize).collect();
+        let mut unique = HashMap::<(usize, usize, usize), usize>::new();
+        for level in (0..input_order.len()).rev() {
+            layer = layer.chunks(2).map(|pair| {
+                let (low, high) = (pair[0], pair[1]);
+                if low == high {
+                    return low;
+                }
+                let next_id = unique.len() + 2;
+                *unique.entry((level, low, high)).or_insert(next_id)
+            }).collect();
+        }
+
+        let terminals = if unique.is_empty() { 1 } else { 2 };
+        Ok(unique.len() + terminals)
+    }
+
+    // For each input, the outputs that change in at least one row when only that input is toggled
+    pub fn sensitivity(&self, chip_name: &str, input_names: &[&str]) -> Result<HashMap<String, HashSet<String>>, EvalError> {
+        let table = self.truth_table(chip_name, input_names)?;
+        let mut result = HashMap::<String, HashSet<String>>::new();
+        for (i, input) in table.inputs.iter().enumerate() {
+            let bit = 1 << (table.inputs.len() - 1 - i);
+            let mut outputs = HashSet::<String>::new();
+            for row in 0..table.rows.len() {
+                for (j, output) in table.outputs.iter().enumerate() {
+                    if table.rows[row].outputs[j] != table.rows[row ^ bit].outputs[j] {
+                        outputs.insert(output.clone());
+                    }
+                }
+            }
+            result.insert(input.clone(), outputs);
+        }
+        Ok(result)
+    }
+
+    // For each output, Some(0) or Some(1) when it has that value in every row of the truth table,
+    // such as `out = AND(x, !x)`, and None when it depends on the inputs
+    pub fn constant_outputs(&self, chip_name: &str, input_names: &[&str]) -> Result<HashMap<String, Option<u8>>, EvalError> {
+        let table = self.truth_table(chip_name, input_names)?;
+        Ok(table.outputs.iter().enumerate().map(|(i, output)| {
+            let first = table.rows.first().is_some_and(|row| row.outputs[i]);
+            let constant = table.rows.iter().all(|row| row.outputs[i] == first);
+            (output.clone(), constant.then_some(first as u8))
+        }).collect())
+    }
+
+    // A hash of the chip's truth table over the given inputs, so chips computing the same function
+    // share a signature however they are built. Output names do not matter, only their order.
+    pub fn function_signature(&self, chip_name: &str, input_names: &[&str]) -> Result<u64, EvalError> {
+        let table = self.truth_table(chip_name, input_names)?;
+
+        // FNV-1a, which unlike the std hashers is stable across runs and Rust versions
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut feed = |byte: u8| {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        };
+        feed(table.inputs.len() as u8);
+        feed(table.outputs.len() as u8);
+        for row in &table.rows {
+            for value in &row.outputs {
+                feed(*value as u8);
+            }
+        }
+        Ok(hash)
+    }
+
+    // Sum-of-products form of the chip's first output, e.g. `out = (!a & b) | (a & !b)`
+    pub fn to_sop(&self, chip_name: &str, input_names: &[&str]) -> Result<String, EvalError> {
+        let table = self.truth_table(chip_name, input_names)?;
+        let output = table.outputs.first().cloned().unwrap_or_else(|| String::from("out"));
+
+        let mut minterms = Vec::<String>::new();
+        for row in &table.rows {
+            if !row.outputs.first().copied().unwrap_or(false) {
+                continue;
+            }
+            let literals: Vec<String> = table.inputs.iter().zip(&row.inputs).map(|(name, value)| {
+                if *value { name.clone() } else { format!("!{}", name) }
+            }).collect();
+            minterms.push(format!("({})", literals.join(" & ")));
+        }
+
+        if minterms.is_empty() {
+            return Ok(format!("{} = 0", output));
+        }
+        Ok(format!("{} = {}", output, minterms.join(" | ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CycleError, parse};
+
+    fn evaluator(width: u32, source: &str) -> ChipEvaluator {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.set_width(width);
+        cpu.load_library(source).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn narrow_widths_only_use_their_own_lanes() {
+        let cpu = evaluator(4, "T\nINPUTS: a, b, c\nout = NAND(a, 1)\n");
+        let table = cpu.truth_table("T", &["a", "b", "c"]).unwrap();
+        assert!(table.rows.iter().all(|row| row.outputs[0] != row.inputs[0]));
+        assert_eq!(cpu.packed_truth_table("T", &["a", "b", "c"]).unwrap(), vec![0b00001111]);
+        assert_eq!(cpu.constant_outputs("T", &["a", "b", "c"]).unwrap()["out"], None);
+        assert_eq!(cpu.bdd_size("T", &["a", "b", "c"]).unwrap(), 3);
+    }
+
+    #[test]
+    fn packed_rows_cross_words_at_widths_not_dividing_64() {
+        let source = "T\nINPUTS: a, b, c, d, e, f, g\nout = XOR(XOR(XOR(a, b), XOR(c, d)), XOR(XOR(e, f), g))\n";
+        let inputs = ["a", "b", "c", "d", "e", "f", "g"];
+        let expected = evaluator(8, source).packed_truth_table("T", &inputs).unwrap();
+        assert_eq!(evaluator(3, source).packed_truth_table("T", &inputs).unwrap(), expected);
+        assert_eq!(evaluator(3, source).truth_table("T", &inputs).unwrap(), evaluator(8, source).truth_table("T", &inputs).unwrap());
+    }
+
+    #[test]
+    fn constant_outputs_finds_dead_logic() {
+        let cpu = evaluator(8, "T\nINPUTS: x\nOUTPUTS: zero, x2\nzero = AND(x, !x)\nx2 = NOT(NOT(x))\n");
+        let constants = cpu.constant_outputs("T", &["x"]).unwrap();
+        assert_eq!(constants["zero"], Some(0));
+        assert_eq!(constants["x2"], None);
+    }
+
+    #[test]
+    fn chips_calling_themselves_are_a_cycle_error() {
+        let cpu = evaluator(8, "A\nINPUTS: a\nout = B(a)\n\nB\nINPUTS: a\nout = A(a)\n");
+        let cycle = EvalError::Cycle(CycleError { chips: vec![String::from("A"), String::from("B"), String::from("A")] });
+        assert_eq!(cpu.packed_truth_table("A", &["a"]), Err(cycle.clone()));
+        assert_eq!(cpu.bdd_size("A", &["a"]), Err(cycle));
+    }
+
+    #[test]
+    fn shared_tables_are_dropped_with_either_chips_dependencies() {
+        let mut cpu = evaluator(8, "A\nINPUTS: a\nout = INV(a)\n\nB\nINPUTS: a\nout = INV(a)\n\nINV\nINPUTS: a\nout = NAND(a, a)\n");
+        let table = cpu.truth_table("A", &["a"]).unwrap();
+        assert_eq!(cpu.truth_table("B", &["a"]).unwrap(), table);
+        assert!(cpu.is_table_cached("B", &["a"]));
+        cpu.load_chip("INV", &parse("out = a").unwrap());
+        assert!(!cpu.is_table_cached("B", &["a"]));
+        assert_eq!(cpu.truth_table("B", &["a"]).unwrap().rows[1].outputs, vec![true]);
+    }
+
+    #[test]
+    fn xor_is_the_sum_of_two_minterms() {
+        let cpu = evaluator(8, "NEVER\nINPUTS: a\nout = AND(a, !a)\n");
+        assert_eq!(cpu.to_sop("XOR", &["a", "b"]).unwrap(), "out = (!a & b) | (a & !b)");
+        assert_eq!(cpu.to_sop("NEVER", &["a"]).unwrap(), "out = 0");
+    }
+
+    #[test]
+    fn ignored_inputs_are_dont_cares() {
+        let cpu = evaluator(8, "T\nINPUTS: a, b, c\nout = AND(a, c)\n");
+        let table = cpu.truth_table_with("T", &["a", "b", "c"], TableOptions { dont_cares: true }).unwrap();
+        assert_eq!(table.dont_cares, vec![false, true, false]);
+        assert_eq!(table.rows.len(), 4);
+        assert!(table.rows.iter().all(|row| !row.inputs[1] && row.outputs[0] == (row.inputs[0] && row.inputs[2])));
+        assert_eq!(table.to_string(), "a b c | out\n0 X 0 |   0\n0 X 1 |   0\n1 X 0 |   0\n1 X 1 |   1\n");
+        assert!(table.is_dont_care(1));
+        assert!(!cpu.truth_table("T", &["a", "b", "c"]).unwrap().is_dont_care(0));
+    }
+
+    #[test]
+    fn equal_functions_share_a_signature() {
+        let cpu = evaluator(8, "XOR2\nINPUTS: a, b\nout = AND(OR(a, b), NAND(a, b))\n");
+        let xor = cpu.function_signature("XOR", &["a", "b"]).unwrap();
+        assert_eq!(cpu.function_signature("XOR2", &["a", "b"]).unwrap(), xor);
+        assert_ne!(cpu.function_signature("AND", &["a", "b"]).unwrap(), xor);
+    }
+
+    #[test]
+    fn carry_in_reaches_both_sum_bits() {
+        let cpu = evaluator(8, "ADD2\nINPUTS: a0, a1, b0, b1, cin\nOUTPUTS: s0, s1, cout\n\
+            s0 = FULLADDER.sum(a0, b0, cin)\nc1 = FULLADDER.carry(a0, b0, cin)\n\
+            s1 = FULLADDER.sum(a1, b1, c1)\ncout = FULLADDER.carry(a1, b1, c1)\n");
+        let outputs = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<HashSet<String>>();
+        let sensitivity = cpu.sensitivity("ADD2", &["a0", "a1", "b0", "b1", "cin"]).unwrap();
+        assert_eq!(sensitivity["cin"], outputs(&["s0", "s1", "cout"]));
+        assert_eq!(sensitivity["a1"], outputs(&["s1", "cout"]));
+        assert_eq!(sensitivity["b0"], outputs(&["s0", "s1", "cout"]));
+    }
+
+    #[test]
+    fn packed_xor_sets_the_rows_with_one_input_high() {
+        let cpu = evaluator(8, "XOR3\nINPUTS: a, b, c\nout = XOR(XOR(a, b), c)\n");
+        assert_eq!(cpu.packed_truth_table("XOR", &["a", "b"]).unwrap(), vec![0b0110]);
+        assert_eq!(cpu.packed_truth_table("XOR3", &["a", "b", "c"]).unwrap(), vec![0b10010110]);
+    }
+
+    #[test]
+    fn xor_has_the_bdd_size_of_its_nand_decomposition() {
+        let cpu = evaluator(8, "XNAND\nINPUTS: a, b\nOUTPUTS: out\nn = NAND(a, b)\nout = NAND(NAND(a, n), NAND(b, n))\n\nXSOP\nINPUTS: a, b\nout = OR(AND(a, !b), AND(!a, b))\n");
+        let xor = cpu.bdd_size("XOR", &["a", "b"]).unwrap();
+        assert_eq!(cpu.bdd_size("XNAND", &["a", "b"]).unwrap(), xor);
+        assert_eq!(cpu.bdd_size("XSOP", &["a", "b"]).unwrap(), xor);
+        assert_ne!(cpu.bdd_size("AND", &["a", "b"]).unwrap(), xor);
+    }
+
+    #[test]
+    fn partial_tables_hold_the_other_inputs() {
+        let cpu = evaluator(8, "T\nINPUTS: a, b, c, d\nout = AND(XOR(a, b), OR(c, d))\n");
+        let outputs = |table: &TruthTable| table.rows.iter().map(|row| row.outputs[0]).collect::<Vec<bool>>();
+        let fixed = HashMap::from([(String::from("c"), 1), (String::from("d"), 0)]);
+        let table = cpu.truth_table_partial("T", &["a", "b"], &fixed).unwrap();
+        assert_eq!(table.inputs, vec!["a", "b"]);
+        assert_eq!(table.rows.len(), 4);
+        assert_eq!(outputs(&table), vec![false, true, true, false]);
+        // d is left at 0 and only the lowest lane of c counts
+        let table = cpu.truth_table_partial("T", &["a", "b"], &HashMap::from([(String::from("c"), 0b10)])).unwrap();
+        assert_eq!(outputs(&table), vec![false; 4]);
+    }
+}
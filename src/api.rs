@@ -0,0 +1,134 @@
+use std::{collections::HashMap, fmt};
+
+use crate::ChipEvaluator;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Port {
+    Input,
+    Output,
+}
+
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Port::Input => write!(f, "input"),
+            Port::Output => write!(f, "output"),
+        }
+    }
+}
+
+// A difference in the interface of a chip found in both versions of a library
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiChange {
+    Added { chip: String, port: Port, name: String },
+    Removed { chip: String, port: Port, name: String },
+    Renamed { chip: String, port: Port, from: String, to: String },
+}
+
+impl ApiChange {
+    // Whether existing callers can break. Only a new output is safe, callers never read it.
+    pub fn is_breaking(&self) -> bool {
+        !matches!(self, ApiChange::Added { port: Port::Output, .. })
+    }
+}
+
+impl fmt::Display for ApiChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiChange::Added { chip, port, name } => write!(f, "{}: {} {} added", chip, port, name),
+            ApiChange::Removed { chip, port, name } => write!(f, "{}: {} {} removed", chip, port, name),
+            ApiChange::Renamed { chip, port, from, to } => write!(f, "{}: {} {} renamed to {}", chip, port, from, to),
+        }
+    }
+}
+
+// A port at the same position under a name neither version shares counts as renamed, as positional
+// callers still bind it the same way
+fn diff_ports(chip_name: &str, port: Port, old: &[String], new: &[String], changes: &mut Vec<ApiChange>) {
+    let mut renamed = Vec::<(&String, &String)>::new();
+    for (from, to) in old.iter().zip(new) {
+        if from != to && !new.contains(from) && !old.contains(to) {
+            renamed.push((from, to));
+        }
+    }
+
+    for name in old {
+        if !new.contains(name) && !renamed.iter().any(|(from, _)| *from == name) {
+            changes.push(ApiChange::Removed { chip: chip_name.to_string(), port, name: name.clone() });
+        }
+    }
+    for (from, to) in &renamed {
+        changes.push(ApiChange::Renamed { chip: chip_name.to_string(), port, from: from.to_string(), to: to.to_string() });
+    }
+    for name in new {
+        if !old.contains(name) && !renamed.iter().any(|(_, to)| *to == name) {
+            changes.push(ApiChange::Added { chip: chip_name.to_string(), port, name: name.clone() });
+        }
+    }
+}
+
+// `s3` is bit 3 of the group `s`, names without a trailing number are single bits
+fn bit_group(name: &str) -> Option<&str> {
+    let stem = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    if stem.is_empty() || stem == name { None } else { Some(stem) }
+}
+
+impl ChipEvaluator {
+    // How many bits wide the value each output is a part of is, by grouping outputs that only differ
+    // in a trailing number: ADD8's s0 to s7 are 8 bits, its carry 1. Lanes are separate evaluations
+    // rather than bits of a value, so they never count.
+    pub fn width_hints(&self, chip_name: &str) -> HashMap<String, usize> {
+        let outputs = self.outputs_of(chip_name);
+        let mut group_sizes = HashMap::<&str, usize>::new();
+        for group in outputs.iter().filter_map(|name| bit_group(name)) {
+            *group_sizes.entry(group).or_default() += 1;
+        }
+        outputs.iter().map(|name| (name.clone(), bit_group(name).map_or(1, |group| group_sizes[group]))).collect()
+    }
+
+    // How the inputs and outputs of the chips loaded in both changed from this library to `other`,
+    // in this library's load order. Chips only loaded in one of them are not compared.
+    pub fn api_diff(&self, other: &ChipEvaluator) -> Vec<ApiChange> {
+        let mut changes = Vec::<ApiChange>::new();
+        for chip_name in self.chip_names() {
+            if !other.contains_chip(chip_name) {
+                continue;
+            }
+            diff_ports(chip_name, Port::Input, &self.inputs_of(chip_name), &other.inputs_of(chip_name), &mut changes);
+            diff_ports(chip_name, Port::Output, &self.outputs_of(chip_name), &other.outputs_of(chip_name), &mut changes);
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_new_input_is_a_breaking_change() {
+        let mut old = ChipEvaluator::new();
+        old.load_library("MUX\nINPUTS: a, b\nOUTPUTS: out\nout = NAND(a, b)\n\nGONE\nout = NAND(x, x)\n").unwrap();
+        let mut new = ChipEvaluator::new();
+        new.load_library("MUX\nINPUTS: a, b, sel\nOUTPUTS: out, debug\nout = NAND(a, sel)\ndebug = b\n").unwrap();
+
+        let changes = old.api_diff(&new);
+        assert_eq!(changes, vec![
+            ApiChange::Added { chip: String::from("MUX"), port: Port::Input, name: String::from("sel") },
+            ApiChange::Added { chip: String::from("MUX"), port: Port::Output, name: String::from("debug") },
+        ]);
+        assert!(changes[0].is_breaking());
+        assert!(!changes[1].is_breaking());
+        assert_eq!(changes[0].to_string(), "MUX: input sel added");
+    }
+
+    #[test]
+    fn carries_are_hinted_as_one_bit() {
+        let cpu = ChipEvaluator::with_stdlib();
+        assert_eq!(cpu.width_hints("FULLADDER"), HashMap::from([(String::from("sum"), 1), (String::from("carry"), 1)]));
+        let hints = cpu.width_hints("ADD8");
+        assert_eq!(hints["carry"], 1);
+        assert!((0..8).all(|i| hints[&format!("s{}", i)] == 8));
+        assert!(cpu.width_hints("MISSING").is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+use std::fmt;
+
+use crate::{token_source, ChipEvaluator, EvalError, Token};
+
+// An `ASSERT <expression> == <literal>` directive found in a chip's source
+#[derive(Debug, Clone)]
+pub struct Assertion {
+    pub chip: String,
+    pub expression: Token,
+    pub expected: Token,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestResult {
+    pub chip: String,
+    pub index: usize, // Position of the assertion within its chip, or row of a vector file, starting at 1
+    pub output: Option<String>, // The output compared, for vector files checking several per row
+    pub expected: u8,
+    pub actual: u8,
+    pub error: Option<EvalError>, // Set when the expression could not be evaluated at all
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.expected == self.actual
+    }
+
+    fn label(&self) -> String {
+        match &self.output {
+            Some(output) => format!("{} #{} {}", self.chip, self.index, output),
+            None => format!("{} #{}", self.chip, self.index),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TestReport {
+    pub results: Vec<TestResult>,
+}
+
+impl TestReport {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|result| result.passed()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.passed()
+    }
+
+    pub fn all_passed(&self) -> bool {
+        self.failed() == 0
+    }
+}
+
+impl fmt::Display for TestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            if let Some(e) = &result.error {
+                writeln!(f, "FAIL {}: {}", result.label(), e)?;
+            }
+            else if result.passed() {
+                writeln!(f, "PASS {}", result.label())?;
+            }
+            else {
+                writeln!(f, "FAIL {}: expected {:#010b}, got {:#010b}", result.label(), result.expected, result.actual)?;
+            }
+        }
+        write!(f, "{} passed, {} failed", self.passed(), self.failed())
+    }
+}
+
+// Separates a chip body from the assertions embedded in it
+pub(crate) fn split_assertions(chip_name: &str, code: &[Token]) -> (Vec<Token>, Vec<Assertion>) {
+    let mut body = Vec::<Token>::new();
+    let mut assertions = Vec::<Assertion>::new();
+    let mut token_iter = code.iter();
+    while let Some(tok) = token_iter.next() {
+        if let Token::Assert = tok {
+            let expression = token_iter.next().cloned().unwrap_or(Token::False);
+            let expected = token_iter.next().cloned().unwrap_or(Token::False);
+            assertions.push(Assertion { chip: chip_name.to_string(), expression, expected });
+        }
+        else {
+            body.push(tok.clone());
+        }
+    }
+    (body, assertions)
+}
+
+impl ChipEvaluator {
+    pub fn assertions(&self) -> &[Assertion] {
+        &self.assertions
+    }
+
+    // Evaluates every loaded assertion, comparing only the lanes within the configured width
+    pub fn run_tests(&self) -> TestReport {
+        let mut report = TestReport::default();
+        let mut index = 0;
+        let mut last_chip = "";
+        for assertion in &self.assertions {
+            index = if assertion.chip == last_chip { index + 1 } else { 1 };
+            last_chip = &assertion.chip;
+
+            let actual = self.operand(&assertion.expression);
+            let expected = self.operand(&assertion.expected);
+            report.results.push(TestResult {
+                chip: assertion.chip.clone(),
+                index,
+                output: None,
+                expected: *expected.as_ref().unwrap_or(&0) & self.mask(),
+                actual: *actual.as_ref().unwrap_or(&0) & self.mask(),
+                error: expected.err().or(actual.err()),
+            });
+        }
+        report
+    }
+
+    // Evaluates one side of an assertion. Assertions have no inputs, so a name would only ever read as 0.
+    fn operand(&self, tok: &Token) -> Result<u8, EvalError> {
+        match tok {
+            Token::Input(_) | Token::IO(..) => Err(EvalError::UnknownSignal(token_source(tok))),
+            Token::Not(inner) => self.operand(inner).map(|value| !value),
+            tok => self.eval_argument(tok, &Default::default(), 0, &mut None)?.map(|(_, value)| value).ok_or_else(|| EvalError::UnknownSignal(token_source(tok))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assertions_report_passes_failures_and_errors() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("T\nINPUTS: a\nout = NOT(a)\nASSERT NOT(1) == 0\nASSERT !NAND(1, 1) == 1\nASSERT AND(1, 0) == 1\nASSERT x == 0\n").unwrap();
+        let report = cpu.run_tests();
+        let outcomes: Vec<bool> = report.results.iter().map(|result| result.passed()).collect();
+        assert_eq!(outcomes, vec![true, true, false, false]);
+        assert_eq!(report.results[3].error, Some(EvalError::UnknownSignal(String::from("x"))));
+        assert_eq!(report.to_string(), "PASS T #1\nPASS T #2\nFAIL T #3: expected 0b11111111, got 0b00000000\nFAIL T #4: Unknown signal: x\n2 passed, 2 failed");
+    }
+}
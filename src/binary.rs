@@ -0,0 +1,254 @@
+use std::{fs::File, io::{self, BufReader, BufWriter, Read, Write}, path::Path};
+
+use crate::{ChipEvaluator, Token};
+
+// Compact encoding of every loaded chip, for large prebuilt libraries that are slow to parse. It is
+// laid out the way bincode lays out a struct, without pulling in serde for the one format:
+//   "NAND" 1, chip count, then per chip its name, token count and tokens
+// Numbers are LEB128 varints, strings a length and UTF-8 bytes, tokens a tag byte and their fields.
+const MAGIC: &[u8; 4] = b"NAND";
+const VERSION: u8 = 1;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn write_number(writer: &mut impl Write, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_number(reader: &mut impl Read) -> io::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid_data("Number too long"))
+}
+
+fn write_string(writer: &mut impl Write, value: &str) -> io::Result<()> {
+    write_number(writer, value.len() as u64)?;
+    writer.write_all(value.as_bytes())
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    let length = read_number(reader)?;
+    let mut bytes = Vec::<u8>::new();
+    reader.take(length).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(bytes).map_err(|_| invalid_data("Invalid UTF-8 in string"))
+}
+
+fn write_names(writer: &mut impl Write, names: &[String]) -> io::Result<()> {
+    write_number(writer, names.len() as u64)?;
+    names.iter().try_for_each(|name| write_string(writer, name))
+}
+
+fn read_names(reader: &mut impl Read) -> io::Result<Vec<String>> {
+    let count = read_number(reader)?;
+    (0..count).map(|_| read_string(reader)).collect()
+}
+
+fn write_token(writer: &mut impl Write, tok: &Token) -> io::Result<()> {
+    match tok {
+        Token::Chip(chip_name) => {
+            writer.write_all(&[0])?;
+            write_string(writer, chip_name)
+        },
+        Token::ChipIO(chip_name, output) => {
+            writer.write_all(&[1])?;
+            write_string(writer, chip_name)?;
+            write_string(writer, output)
+        },
+        Token::Input(source) => {
+            writer.write_all(&[2])?;
+            write_string(writer, source)
+        },
+        Token::IO(input, source) => {
+            writer.write_all(&[3])?;
+            write_string(writer, input)?;
+            write_string(writer, source)
+        },
+        Token::Output(name) => {
+            writer.write_all(&[4])?;
+            write_string(writer, name)
+        },
+        Token::True => writer.write_all(&[5]),
+        Token::False => writer.write_all(&[6]),
+        Token::Const(value) => {
+            writer.write_all(&[7])?;
+            write_number(writer, *value)
+        },
+        Token::Assign => writer.write_all(&[8]),
+        Token::LParen => writer.write_all(&[9]),
+        Token::RParen => writer.write_all(&[10]),
+        Token::Comma => writer.write_all(&[11]),
+        Token::Assert => writer.write_all(&[12]),
+        Token::Inputs(names) => {
+            writer.write_all(&[13])?;
+            write_names(writer, names)
+        },
+        Token::Outputs(names) => {
+            writer.write_all(&[14])?;
+            write_names(writer, names)
+        },
+        Token::DefaultOutput(name) => {
+            writer.write_all(&[15])?;
+            write_string(writer, name)
+        },
+        Token::Bang => writer.write_all(&[16]),
+        Token::Not(inner) => {
+            writer.write_all(&[17])?;
+            write_token(writer, inner)
+        },
+        Token::Expression(e_codes) => {
+            writer.write_all(&[18])?;
+            write_tokens(writer, e_codes)
+        },
+        Token::Doc(text) => {
+            writer.write_all(&[19])?;
+            write_string(writer, text)
+        },
+        Token::Attribute(key, value) => {
+            writer.write_all(&[20])?;
+            write_string(writer, key)?;
+            write_string(writer, value)
+        },
+    }
+}
+
+fn write_tokens(writer: &mut impl Write, code: &[Token]) -> io::Result<()> {
+    write_number(writer, code.len() as u64)?;
+    code.iter().try_for_each(|tok| write_token(writer, tok))
+}
+
+fn read_token(reader: &mut impl Read) -> io::Result<Token> {
+    let mut tag = [0u8];
+    reader.read_exact(&mut tag)?;
+    Ok(match tag[0] {
+        0 => Token::Chip(read_string(reader)?),
+        1 => Token::ChipIO(read_string(reader)?, read_string(reader)?),
+        2 => Token::Input(read_string(reader)?),
+        3 => Token::IO(read_string(reader)?, read_string(reader)?),
+        4 => Token::Output(read_string(reader)?),
+        5 => Token::True,
+        6 => Token::False,
+        7 => Token::Const(read_number(reader)?),
+        8 => Token::Assign,
+        9 => Token::LParen,
+        10 => Token::RParen,
+        11 => Token::Comma,
+        12 => Token::Assert,
+        13 => Token::Inputs(read_names(reader)?),
+        14 => Token::Outputs(read_names(reader)?),
+        15 => Token::DefaultOutput(read_string(reader)?),
+        16 => Token::Bang,
+        17 => Token::Not(Box::new(read_token(reader)?)),
+        18 => Token::Expression(read_tokens(reader)?),
+        19 => Token::Doc(read_string(reader)?),
+        20 => Token::Attribute(read_string(reader)?, read_string(reader)?),
+        tag => return Err(invalid_data(&format!("Unknown token tag {}", tag))),
+    })
+}
+
+fn read_tokens(reader: &mut impl Read) -> io::Result<Vec<Token>> {
+    let count = read_number(reader)?;
+    (0..count).map(|_| read_token(reader)).collect()
+}
+
+impl ChipEvaluator {
+    // Writes every loaded chip in load order, along with its assertions, in the binary format read
+    // by read_bincode
+    pub fn write_bincode(&self, mut writer: impl Write) -> io::Result<()> {
+        let chip_names = self.chip_names();
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        write_number(&mut writer, chip_names.len() as u64)?;
+        for chip_name in chip_names {
+            // Assertions go back into the body, as they were before load_chip split them out
+            let mut code = self.chips[chip_name].clone();
+            for assertion in self.assertions.iter().filter(|assertion| assertion.chip == *chip_name) {
+                code.extend([Token::Assert, assertion.expression.clone(), assertion.expected.clone()]);
+            }
+            write_string(&mut writer, chip_name)?;
+            write_tokens(&mut writer, &code)?;
+        }
+        writer.flush()
+    }
+
+    // Loads every chip written by write_bincode, returning their names
+    pub fn read_bincode(&mut self, mut reader: impl Read) -> io::Result<Vec<String>> {
+        let mut header = [0u8; 5];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(invalid_data("Not a NandScript binary library"));
+        }
+        if header[4] != VERSION {
+            return Err(invalid_data(&format!("Unsupported binary library version {}", header[4])));
+        }
+
+        let count = read_number(&mut reader)?;
+        let mut chip_names = Vec::<String>::new();
+        for _ in 0..count {
+            let chip_name = read_string(&mut reader)?;
+            let code = read_tokens(&mut reader)?;
+            self.load_chip(&chip_name, &code);
+            chip_names.push(chip_name);
+        }
+        Ok(chip_names)
+    }
+
+    pub fn save_bincode(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write_bincode(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load_bincode(&mut self, path: impl AsRef<Path>) -> io::Result<Vec<String>> {
+        self.read_bincode(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs};
+
+    use super::*;
+    use crate::Executable;
+
+    #[test]
+    fn round_trips_evaluate_like_the_original() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("/// Majority vote\nMAJ\nINPUTS: a, b, c\nout = OR(AND(a, b), AND(!c, REDUCE(OR, a, b, 0x0f)))\nASSERT MAJ(1, 1, 0) == 1\n").unwrap();
+        let path = std::env::temp_dir().join(format!("nandscript-bincode-{}.bin", std::process::id()));
+        cpu.save_bincode(&path).unwrap();
+        let mut loaded = ChipEvaluator::new();
+        assert_eq!(loaded.load_bincode(&path).unwrap(), cpu.chip_names());
+        fs::remove_file(path).unwrap();
+
+        let inputs = HashMap::from([(String::from("a"), 0b1100), (String::from("b"), 0b1010), (String::from("c"), 0b0110), (String::from("sel"), 0b1001)]);
+        for chip_name in cpu.chip_names() {
+            assert_eq!(loaded.chips[chip_name], cpu.chips[chip_name], "{}", chip_name);
+            assert_eq!(loaded.eval(loaded.chips[chip_name].clone(), &inputs).unwrap(), cpu.eval(cpu.chips[chip_name].clone(), &inputs).unwrap(), "{}", chip_name);
+        }
+        assert_eq!(loaded.run_tests().results, cpu.run_tests().results);
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        let mut cpu = ChipEvaluator::new();
+        assert_eq!(cpu.read_bincode(&b"MAJ\nINPUTS: a"[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,114 @@
+use std::{convert::TryFrom, fmt};
+
+use crate::{ChipEvaluator, NandError, Token, declared_inputs, declared_outputs, inferred_inputs, parse_library, statement_outputs};
+
+// A single parsed chip along with its ports
+#[derive(Debug, Clone)]
+pub struct Chip {
+    pub name: String,
+    pub code: Vec<Token>,
+    pub inputs: Vec<String>,  // Declared inputs, else every name read before being assigned
+    pub outputs: Vec<String>, // Declared outputs, else every statement output
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChipError {
+    Syntax(NandError),
+    NoChip,
+    MultipleChips(Vec<String>),
+    UndrivenOutputs { chip: String, outputs: Vec<String> },
+}
+
+impl fmt::Display for ChipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChipError::Syntax(e) => write!(f, "{}", e),
+            ChipError::NoChip => write!(f, "No chip found in source"),
+            ChipError::MultipleChips(chips) => write!(f, "Expected a single chip, found {}", chips.join(", ")),
+            ChipError::UndrivenOutputs { chip, outputs } => write!(f, "Chip {} never assigns declared outputs: {}", chip, outputs.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for ChipError {}
+
+impl TryFrom<&str> for Chip {
+    type Error = ChipError;
+
+    // Parses source holding exactly one chip, in the same format as a library
+    fn try_from(source: &str) -> Result<Self, Self::Error> {
+        let mut chips: Vec<(String, Vec<Token>)> = parse_library(source).map_err(ChipError::Syntax)?;
+        if chips.len() > 1 {
+            let mut chip_names: Vec<String> = chips.into_iter().map(|(name, _)| name).collect();
+            chip_names.sort();
+            return Err(ChipError::MultipleChips(chip_names));
+        }
+        let (name, code) = chips.pop().ok_or(ChipError::NoChip)?;
+
+        let outputs = match declared_outputs(&code) {
+            Some(outputs) => {
+                let assigned = statement_outputs(&code);
+                let undriven: Vec<String> = outputs.iter().filter(|out| !assigned.contains(out)).cloned().collect();
+                if !undriven.is_empty() {
+                    return Err(ChipError::UndrivenOutputs { chip: name, outputs: undriven });
+                }
+                outputs
+            },
+            None => statement_outputs(&code),
+        };
+        let inputs = declared_inputs(&code).unwrap_or_else(|| inferred_inputs(&code));
+
+        Ok(Chip { name, code, inputs, outputs })
+    }
+}
+
+impl ChipEvaluator {
+    pub fn load(&mut self, chip: &Chip) {
+        self.load_chip(&chip.name, &chip.code);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn inferred_inputs_match_the_evaluator() {
+        let source = "T\nx = NAND(b, a)\ny = NOT(REDUCE(AND, x, c, 1))\nout = OR(y, !d)\n";
+        let chip = Chip::try_from(source).unwrap();
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load(&chip);
+        assert_eq!(chip.inputs, vec!["b", "a", "c", "d"]);
+        assert_eq!(chip.inputs, cpu.inputs_of("T"));
+    }
+
+    #[test]
+    fn declared_outputs_must_be_assigned() {
+        let source = "HALF\nINPUTS: a, b\nOUTPUTS: sum, carry\nsum = NAND(a, b)\n";
+        let error = Chip::try_from(source).unwrap_err();
+        assert_eq!(error, ChipError::UndrivenOutputs { chip: String::from("HALF"), outputs: vec![String::from("carry")] });
+        assert_eq!(error.to_string(), "Chip HALF never assigns declared outputs: carry");
+
+        let mut cpu = ChipEvaluator::new();
+        cpu.load_library(source).unwrap();
+        assert_eq!(cpu.check_outputs("HALF").unwrap(), vec!["carry"]);
+        assert_eq!(cpu.check(), vec!["HALF: output carry is never assigned"]);
+    }
+
+    #[test]
+    fn chips_evaluate_once_loaded() {
+        let chip: Chip = "HALF\nINPUTS: a, b\nOUTPUTS: sum, carry\nsum = XOR(a, b)\ncarry = AND(a, b)\n".try_into().unwrap();
+        assert_eq!(chip.name, "HALF");
+        assert_eq!(chip.inputs, vec!["a", "b"]);
+        assert_eq!(chip.outputs, vec!["sum", "carry"]);
+
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load(&chip);
+        let inputs = HashMap::from([(String::from("a"), 0b1100), (String::from("b"), 0b1010)]);
+        let outputs = &cpu.eval_all(&["HALF"], &inputs).unwrap()["HALF"];
+        assert_eq!(outputs["sum"], 0b0110);
+        assert_eq!(outputs["carry"], 0b1000);
+    }
+}
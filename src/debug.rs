@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use crate::{ChipEvaluator, EvalError, Token};
+
+// Evaluates a chip one statement at a time, so the inputs and the outputs computed so far can be
+// inspected between steps. Sub-chip calls within a statement run to completion in one step.
+pub struct DebugSession<'a> {
+    evaluator: &'a ChipEvaluator,
+    chip_name: String,
+    statements: Vec<(String, Token)>,
+    next: usize,
+    inputs: HashMap<String, u8>,
+    outputs: HashMap<String, u8>,
+}
+
+impl<'a> DebugSession<'a> {
+    pub fn inputs(&self) -> &HashMap<String, u8> {
+        &self.inputs
+    }
+
+    // The outputs and locals assigned by the steps taken so far
+    pub fn outputs(&self) -> &HashMap<String, u8> {
+        &self.outputs
+    }
+
+    // The output name and expression of the statement the next step evaluates
+    pub fn next_statement(&self) -> Option<(&str, &Token)> {
+        self.statements.get(self.next).map(|(name, tok)| (name.as_str(), tok))
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next >= self.statements.len()
+    }
+
+    // Evaluates the next statement, returning the name it assigned and its value, or None once
+    // every statement has run
+    pub fn step(&mut self) -> Result<Option<(String, u8)>, EvalError> {
+        let Some((name, tok)) = self.statements.get(self.next) else {
+            return Ok(None);
+        };
+        // Earlier statements can be read by later ones like inputs
+        let mut scope = self.inputs.clone();
+        scope.extend(self.outputs.iter().map(|(name, value)| (name.clone(), *value)));
+        let value = self.evaluator.eval_argument(tok, &scope, 0, &mut None).map_err(|e| e.in_statement(name, tok).in_chip(&self.chip_name))?.map(|(_, value)| value).unwrap_or(0);
+
+        self.outputs.insert(name.clone(), value);
+        self.next += 1;
+        Ok(Some((name.clone(), value)))
+    }
+
+    // Runs the remaining statements and returns every output
+    pub fn finish(mut self) -> Result<HashMap<String, u8>, EvalError> {
+        while self.step()?.is_some() {}
+        Ok(self.outputs)
+    }
+}
+
+impl ChipEvaluator {
+    pub fn debug(&self, chip_name: &str, inputs: &HashMap<String, u8>) -> Result<DebugSession<'_>, EvalError> {
+        let code = self.chips.get(chip_name).ok_or_else(|| EvalError::UnknownChip(chip_name.to_string()))?;
+
+        let mut statements = Vec::<(String, Token)>::new();
+        let mut out_name: Option<String> = None;
+        let mut token_iter = code.iter();
+        while let Some(tok) = token_iter.next() {
+            match tok {
+                Token::Output(name) => out_name = Some(name.clone()),
+                Token::Input(_) | Token::True | Token::False | Token::Const(_) | Token::Not(_) | Token::Expression(_) => {
+                    statements.push((out_name.take().unwrap_or_else(|| String::from("out")), tok.clone()));
+                },
+                Token::Assert => {
+                    // Assertions are only run by run_tests, skip the expression and expected value
+                    token_iter.next();
+                    token_iter.next();
+                },
+                Token::Chip(_) | Token::ChipIO(_, _) | Token::IO(_, _) | Token::Assign | Token::LParen | Token::RParen | Token::Comma
+                    | Token::Inputs(_) | Token::Outputs(_) | Token::DefaultOutput(_) | Token::Doc(_) | Token::Attribute(_, _) | Token::Bang => {},
+            }
+        }
+
+        Ok(DebugSession { evaluator: self, chip_name: chip_name.to_string(), statements, next: 0, inputs: inputs.clone(), outputs: HashMap::new() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_step_one_statement_at_a_time() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("T\nINPUTS: a, b\nn = NAND(a, b)\nout = NOT(n)\n").unwrap();
+        let inputs = HashMap::from([(String::from("a"), 0b1100), (String::from("b"), 0b1010)]);
+        let mut session = cpu.debug("T", &inputs).unwrap();
+        assert_eq!(session.inputs(), &inputs);
+        assert_eq!(session.next_statement().map(|(name, _)| name), Some("n"));
+
+        assert_eq!(session.step(), Ok(Some((String::from("n"), !0b1000))));
+        assert_eq!(session.outputs(), &HashMap::from([(String::from("n"), !0b1000)]));
+        assert!(!session.is_done());
+
+        assert_eq!(session.step(), Ok(Some((String::from("out"), 0b1000))));
+        assert_eq!(session.outputs()["out"], 0b1000);
+        assert!(session.is_done());
+        assert_eq!(session.step(), Ok(None));
+    }
+}
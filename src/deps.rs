@@ -0,0 +1,268 @@
+use std::{collections::{HashMap, HashSet}, fmt};
+
+use crate::{ChipEvaluator, EvalError, Token, TokenVisitor, expand_reduce, lex_literal, walk_body};
+
+// A dependency cycle, listed in the order the chips refer to each other
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleError {
+    pub chips: Vec<String>,
+}
+
+impl fmt::Display for CycleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Chip dependency cycle: {}", self.chips.join(" -> "))
+    }
+}
+
+impl std::error::Error for CycleError {}
+
+// Every chip a body calls directly, in order of first appearance
+pub fn referenced_chips(code: &[Token]) -> Vec<String> {
+    calls_in(code, "NAND")
+}
+
+fn calls_in(code: &[Token], primitive: &str) -> Vec<String> {
+    let mut calls = Calls { primitive, result: vec![] };
+    walk_body(code, &mut calls);
+    calls.result
+}
+
+struct Calls<'a> {
+    primitive: &'a str,
+    result: Vec<String>,
+}
+
+impl TokenVisitor for Calls<'_> {
+    fn visit_chip(&mut self, chip_name: &str, _output: Option<&str>) {
+        if chip_name != self.primitive && !self.result.iter().any(|x| x == chip_name) {
+            self.result.push(chip_name.to_string());
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Visit {
+    InProgress,
+    Done,
+}
+
+impl ChipEvaluator {
+    // Like referenced_chips, leaving out the configured primitive rather than NAND
+    fn referenced_chips(&self, code: &[Token]) -> Vec<String> {
+        calls_in(code, &self.primitive)
+    }
+
+    // Every chip transitively referenced by `chip_name`, including ones that are not loaded
+    pub fn dependencies(&self, chip_name: &str) -> HashSet<String> {
+        let mut result = HashSet::<String>::new();
+        let mut pending = vec![chip_name.to_string()];
+        while let Some(current) = pending.pop() {
+            if let Some(code) = self.chips.get(&current) {
+                for dependency in self.referenced_chips(code) {
+                    if result.insert(dependency.clone()) {
+                        pending.push(dependency);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    // Fails when the chip calls itself through other chips, or else with the first chip by name it
+    // needs that is native or not loaded. Chips may call chips loaded after them, so this can only be
+    // known once they are used.
+    pub(crate) fn check_flattenable(&self, chip_name: &str) -> Result<(), EvalError> {
+        if !self.chips.contains_key(chip_name) {
+            return Err(EvalError::UnknownChip(chip_name.to_string()));
+        }
+        self.visit(chip_name, &mut HashMap::new(), &mut Vec::new(), &mut Vec::new()).map_err(EvalError::Cycle)?;
+
+        let mut dependencies: Vec<String> = self.dependencies(chip_name).into_iter().collect();
+        dependencies.sort();
+        for dependency in dependencies {
+            if self.natives.contains_key(&dependency) {
+                return Err(EvalError::CannotFlattenNative(dependency));
+            }
+            if !self.chips.contains_key(&dependency) {
+                return Err(EvalError::UnknownChip(dependency));
+            }
+        }
+        Ok(())
+    }
+
+    // Input names the chip reads from in a way that can reach a gate, following bindings into sub-chips.
+    // Declared inputs missing from the result have no effect on any output.
+    pub fn used_inputs(&self, chip_name: &str) -> HashSet<String> {
+        self.used_inputs_visiting(chip_name, &mut HashSet::new())
+    }
+
+    fn used_inputs_visiting(&self, chip_name: &str, visiting: &mut HashSet<String>) -> HashSet<String> {
+        let mut result = HashSet::<String>::new();
+        if let Some(code) = self.chips.get(chip_name) {
+            visiting.insert(chip_name.to_string());
+            // Names assigned by earlier statements are locals rather than inputs
+            let mut locals = HashSet::<String>::new();
+            let mut assigning: Option<&String> = None;
+            for tok in code {
+                if let Token::Output(out) = tok {
+                    assigning = Some(out);
+                    continue;
+                }
+                let mut statement_inputs = HashSet::<String>::new();
+                self.collect_used_inputs(tok, "", None, &mut statement_inputs, visiting);
+                result.extend(statement_inputs.into_iter().filter(|name| !locals.contains(name)));
+                if let Some(out) = assigning.take() {
+                    locals.insert(out.clone());
+                }
+            }
+            visiting.remove(chip_name);
+        }
+        result
+    }
+
+    // `param` is the input `tok` binds to when passed by position, and `callee_inputs` the inputs
+    // used by the chip it is passed to, None when every input counts
+    fn collect_used_inputs(&self, tok: &Token, param: &str, callee_inputs: Option<&HashSet<String>>, result: &mut HashSet<String>, visiting: &mut HashSet<String>) {
+        let reaches_callee = |name: &str| callee_inputs.is_none_or(|used| used.contains(name));
+        match tok {
+            Token::IO(x, y) if lex_literal(y).is_none() && reaches_callee(x) => {
+                result.insert(y.clone());
+            },
+            Token::Input(y) if reaches_callee(param) => {
+                result.insert(y.clone());
+            },
+            Token::Expression(e_codes) => {
+                if let Some(folded) = expand_reduce(e_codes) {
+                    return self.collect_used_inputs(&folded, param, callee_inputs, result, visiting);
+                }
+                if !reaches_callee(param) {
+                    return;
+                }
+                let chip_name = match e_codes.first() {
+                    Some(Token::Chip(chip_name)) => chip_name,
+                    Some(Token::ChipIO(chip_name, _)) => chip_name,
+                    _ => return,
+                };
+                // The primitive, native and unloaded chips, and chips already being expanded, are taken to use everything
+                let used = if self.chips.contains_key(chip_name) && !visiting.contains(chip_name) {
+                    Some(self.used_inputs_visiting(chip_name, visiting))
+                }
+                else {
+                    None
+                };
+                for (i, arg) in e_codes.iter().skip(1).enumerate() {
+                    self.collect_used_inputs(arg, &self.param_name(chip_name, i), used.as_ref(), result, visiting);
+                }
+            },
+            Token::Not(inner) => self.collect_used_inputs(inner, param, callee_inputs, result, visiting),
+            _ => {},
+        }
+    }
+
+    // Checks every chip referenced by a loaded chip is loaded too, listing the missing ones
+    pub fn resolve(&self) -> Result<(), Vec<String>> {
+        let mut missing = Vec::<String>::new();
+        for code in self.chips.values() {
+            for chip_name in self.referenced_chips(code) {
+                if !self.chips.contains_key(&chip_name) && !self.natives.contains_key(&chip_name) && !missing.contains(&chip_name) {
+                    missing.push(chip_name);
+                }
+            }
+        }
+        if missing.is_empty() {
+            return Ok(());
+        }
+        missing.sort();
+        Err(missing)
+    }
+
+    // All loaded chips, each one listed after the chips it depends on
+    pub fn topological_order(&self) -> Result<Vec<String>, CycleError> {
+        let mut chip_names: Vec<&String> = self.chips.keys().collect();
+        chip_names.sort();
+
+        let mut order = Vec::<String>::new();
+        let mut visits = HashMap::<String, Visit>::new();
+        let mut path = Vec::<String>::new();
+        for chip_name in chip_names {
+            self.visit(chip_name, &mut visits, &mut path, &mut order)?;
+        }
+        Ok(order)
+    }
+
+    fn visit(&self, chip_name: &str, visits: &mut HashMap<String, Visit>, path: &mut Vec<String>, order: &mut Vec<String>) -> Result<(), CycleError> {
+        match visits.get(chip_name) {
+            Some(Visit::Done) => return Ok(()),
+            Some(Visit::InProgress) => {
+                let start = path.iter().position(|x| x == chip_name).unwrap();
+                let mut chips = path[start..].to_vec();
+                chips.push(chip_name.to_string());
+                return Err(CycleError { chips });
+            },
+            None => {},
+        }
+        // Missing chips are not part of the order
+        let code = match self.chips.get(chip_name) {
+            Some(code) => code,
+            None => return Ok(()),
+        };
+
+        visits.insert(chip_name.to_string(), Visit::InProgress);
+        path.push(chip_name.to_string());
+        for dependency in self.referenced_chips(code) {
+            self.visit(&dependency, visits, path, order)?;
+        }
+        path.pop();
+        visits.insert(chip_name.to_string(), Visit::Done);
+        order.push(chip_name.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(chip_names: &[&str]) -> HashSet<String> {
+        chip_names.iter().map(|chip_name| chip_name.to_string()).collect()
+    }
+
+    #[test]
+    fn chains_are_ordered_dependencies_first() {
+        let mut cpu = ChipEvaluator::new();
+        cpu.load_library("C\nINPUTS: a\nout = B(a)\n\nB\nINPUTS: a\nout = A(a)\n\nA\nINPUTS: a\nout = NAND(a, a)\n").unwrap();
+        assert_eq!(cpu.dependencies("C"), names(&["B", "A"]));
+        assert!(cpu.dependencies("A").is_empty());
+        assert_eq!(cpu.topological_order().unwrap(), vec!["A", "B", "C"]);
+    }
+
+    #[test]
+    fn cycles_are_named_in_call_order() {
+        let mut cpu = ChipEvaluator::new();
+        cpu.load_library("X\nINPUTS: a\nout = Y(a)\n\nY\nINPUTS: a\nout = NAND(Z(a), X(a))\n\nZ\nINPUTS: a\nout = NAND(a, a)\n").unwrap();
+        assert_eq!(cpu.dependencies("X"), names(&["X", "Y", "Z"]));
+        let cycle = cpu.topological_order().unwrap_err();
+        assert_eq!(cycle.chips, vec!["X", "Y", "X"]);
+        assert_eq!(cycle.to_string(), "Chip dependency cycle: X -> Y -> X");
+    }
+
+    #[test]
+    fn used_inputs_leave_out_ignored_ones() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("T\nINPUTS: a, b, c\nunused = NAND(c, c)\nout = AND(a, b)\n\nU\nINPUTS: x, y, z\nout = T(x, z, y)\n").unwrap();
+        // c only reaches a local, which still counts as reaching a gate
+        assert_eq!(cpu.used_inputs("T"), names(&["a", "b", "c"]));
+        cpu.load_library("T\nINPUTS: a, b, c\nout = AND(a, b)\n").unwrap();
+        assert_eq!(cpu.used_inputs("T"), names(&["a", "b"]));
+        assert_eq!(cpu.used_inputs("U"), names(&["x", "z"]));
+    }
+
+    #[test]
+    fn resolve_lists_unloaded_chips() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("T\nINPUTS: a, b\nout = AND(FOO(a), BAR.x(b))\n\nU\nINPUTS: a\nout = FOO(NOT(a))\n").unwrap();
+        assert_eq!(cpu.resolve(), Err(vec![String::from("BAR"), String::from("FOO")]));
+        cpu.load_library("FOO\nINPUTS: a\nout = a\n\nBAR\nINPUTS: a\nx = a\n").unwrap();
+        assert_eq!(cpu.resolve(), Ok(()));
+    }
+}
@@ -0,0 +1,159 @@
+use std::{collections::HashMap, fs, io, path::{Path, PathBuf}};
+
+use crate::{ChipEvaluator, Token, parse_library};
+
+// The `.nand` files directly inside a directory, sorted by name
+pub fn nand_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::<PathBuf>::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|extension| extension == "nand") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+fn invalid_data(path: &Path, message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), message))
+}
+
+// Takes the `#include "file"` lines out of a source, returning the files they name. The lines are
+// left blank so line numbers in syntax errors still match the file.
+fn split_includes(path: &Path, source: &str) -> io::Result<(String, Vec<String>)> {
+    let mut code = String::new();
+    let mut includes = Vec::<String>::new();
+    for line in source.lines() {
+        if let Some(rest) = line.trim().strip_prefix("#include") {
+            let file = rest.trim().strip_prefix('"').and_then(|rest| rest.strip_suffix('"')).ok_or_else(|| invalid_data(path, format!("Expected a quoted file name: {}", line.trim())))?;
+            includes.push(file.to_string());
+        }
+        else {
+            code += line;
+        }
+        code.push('\n');
+    }
+    Ok((code, includes))
+}
+
+// Reads a library file after the files it includes, adding each chip along with the file defining it.
+// A file included twice is only read once, one that ends up including itself is an error.
+fn read_included(path: &Path, including: &mut Vec<PathBuf>, chips: &mut Vec<(PathBuf, String, Vec<Token>)>) -> io::Result<()> {
+    let path = fs::canonicalize(path)?;
+    if including.contains(&path) {
+        let cycle: Vec<String> = including.iter().chain([&path]).map(|path| path.display().to_string()).collect();
+        return Err(invalid_data(&path, format!("Cyclic include: {}", cycle.join(" -> "))));
+    }
+    if chips.iter().any(|(origin, _, _)| *origin == path) {
+        return Ok(());
+    }
+
+    let source = fs::read_to_string(&path)?;
+    let (code, includes) = split_includes(&path, &source)?;
+    including.push(path.clone());
+    for file in includes {
+        // Relative to the including file, not the working directory
+        let included = path.parent().unwrap_or(Path::new("")).join(file);
+        read_included(&included, including, chips)?;
+    }
+    including.pop();
+
+    let own = parse_library(&code).map_err(|e| invalid_data(&path, e))?;
+    chips.extend(own.into_iter().map(|(chip_name, code)| (path.clone(), chip_name, code)));
+    Ok(())
+}
+
+// The chips of a library file and the files it includes, each file's in source order and included
+// files first, along with the file defining them. Syntax errors are InvalidData, prefixed with the path.
+pub fn read_library(path: &Path) -> io::Result<Vec<(PathBuf, String, Vec<Token>)>> {
+    let mut chips = Vec::<(PathBuf, String, Vec<Token>)>::new();
+    read_included(path, &mut Vec::new(), &mut chips)?;
+    Ok(chips)
+}
+
+// The chips of every `.nand` file in a directory along with the file defining them, in file name order.
+// A chip defined by two of the files is InvalidData. Files included by others are only read once.
+pub(crate) fn read_dir(dir: &Path) -> io::Result<Vec<(PathBuf, String, Vec<Token>)>> {
+    let mut chips = Vec::<(PathBuf, String, Vec<Token>)>::new();
+    let mut origins = HashMap::<String, PathBuf>::new();
+    for path in nand_files(dir)? {
+        for (path, chip_name, code) in read_library(&path)? {
+            match origins.insert(chip_name.clone(), path.clone()) {
+                Some(origin) if origin == path => continue,
+                Some(origin) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Chip {} is defined in both {} and {}", chip_name, origin.display(), path.display()))),
+                None => {},
+            }
+            chips.push((path, chip_name, code));
+        }
+    }
+    Ok(chips)
+}
+
+impl ChipEvaluator {
+    // Loads every chip of a library file, returning their names. Chips of files pulled in with
+    // `#include "file"` come first.
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> io::Result<Vec<String>> {
+        let mut chip_names = Vec::<String>::new();
+        for (_, chip_name, code) in read_library(path.as_ref())? {
+            self.load_chip(&chip_name, &code);
+            chip_names.push(chip_name);
+        }
+        Ok(chip_names)
+    }
+
+    // Loads every `.nand` file in a directory, in file name order, returning the chips loaded.
+    // A chip defined by two of the files is an InvalidData error and nothing is loaded. Files
+    // included by others in the directory are only loaded once.
+    pub fn load_dir(&mut self, dir: impl AsRef<Path>) -> io::Result<Vec<String>> {
+        let mut chip_names = Vec::<String>::new();
+        for (_, chip_name, code) in read_dir(dir.as_ref())? {
+            self.load_chip(&chip_name, &code);
+            chip_names.push(chip_name);
+        }
+        Ok(chip_names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EvalError;
+
+    #[test]
+    fn included_files_load_first_and_cycles_are_errors() {
+        let dir = std::env::temp_dir().join(format!("nandscript-include-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("lib").join("gates.nand"), "INV\nINPUTS: a\nout = NAND(a, a)\n").unwrap();
+        fs::write(dir.join("top.nand"), "#include \"lib/gates.nand\"\nTOP\nINPUTS: a\nout = INV(INV(a))\n").unwrap();
+
+        let mut cpu = ChipEvaluator::new();
+        assert_eq!(cpu.load_file(dir.join("top.nand")).unwrap(), vec!["INV", "TOP"]);
+        let inputs = HashMap::from([(String::from("a"), 0b1010)]);
+        assert_eq!(cpu.eval_all(&["TOP"], &inputs).unwrap()["TOP"]["out"], 0b1010);
+
+        fs::write(dir.join("lib").join("gates.nand"), "#include \"../top.nand\"\nINV\nINPUTS: a\nout = NAND(a, a)\n").unwrap();
+        let error = ChipEvaluator::new().load_file(dir.join("top.nand")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("Cyclic include"), "{}", error);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn chips_may_call_chips_from_files_loaded_later() {
+        let dir = std::env::temp_dir().join(format!("nandscript-forward-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.nand"), "TOP\nINPUTS: a\nout = INV(INV(a))\n\nBROKEN\nINPUTS: a\nout = GONE(a)\n").unwrap();
+        fs::write(dir.join("b.nand"), "INV\nINPUTS: a\nout = NAND(a, a)\n").unwrap();
+
+        let mut cpu = ChipEvaluator::new();
+        assert_eq!(cpu.load_dir(&dir).unwrap(), vec!["TOP", "BROKEN", "INV"]);
+        fs::remove_dir_all(dir).unwrap();
+        let inputs = HashMap::from([(String::from("a"), 0b1010)]);
+        assert_eq!(cpu.eval_all(&["TOP"], &inputs).unwrap()["TOP"]["out"], 0b1010);
+        assert_eq!(cpu.resolve(), Err(vec![String::from("GONE")]));
+        assert_eq!(cpu.eval_all(&["BROKEN"], &inputs).unwrap_err().root_cause(), &EvalError::UnknownChip(String::from("GONE")));
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{NandError, Token, is_chip_header, parse_annotations, parse_statements, token_source, tokenize_comments};
+
+// One line per statement or declaration of parsed code
+fn render(code: &[Token]) -> Vec<String> {
+    let mut lines = Vec::<String>::new();
+    let mut out_name: Option<&String> = None;
+    let mut token_iter = code.iter();
+    while let Some(tok) = token_iter.next() {
+        match tok {
+            Token::Output(name) => out_name = Some(name),
+            Token::Input(_) | Token::True | Token::False | Token::Const(_) | Token::Not(_) | Token::Expression(_) => {
+                lines.push(match out_name.take() {
+                    Some(name) => format!("{} = {}", name, token_source(tok)),
+                    None => token_source(tok),
+                });
+            },
+            Token::Assert => {
+                let expression = token_iter.next().map(token_source).unwrap_or_default();
+                let expected = token_iter.next().map(token_source).unwrap_or_default();
+                lines.push(format!("ASSERT {} == {}", expression, expected));
+            },
+            Token::Inputs(_) | Token::Outputs(_) | Token::DefaultOutput(_) | Token::Doc(_) | Token::Attribute(_, _) => lines.push(token_source(tok)),
+            Token::Chip(_) | Token::ChipIO(_, _) | Token::IO(_, _) | Token::Assign | Token::LParen | Token::RParen
+                | Token::Comma | Token::Bang => {},
+        }
+    }
+    lines
+}
+
+// Renders one statement from its source and tokens. Parsing turns `x, y = ...` into `x = ...`
+// followed by `y = x`, which is put back together.
+fn render_statement(statement: &str, tokens: &[String]) -> Result<Vec<String>, NandError> {
+    let mut lines = render(&parse_statements(statement)?);
+    let mut fan_out = Vec::<&String>::new();
+    let mut names = tokens.iter();
+    let first = names.next();
+    while let (Some(comma), Some(name)) = (names.next(), names.next()) {
+        if comma != "," {
+            break;
+        }
+        fan_out.push(name);
+    }
+    let first = match first {
+        Some(first) if !fan_out.is_empty() && lines.len() > fan_out.len() => first,
+        _ => return Ok(lines),
+    };
+    let copies = fan_out.iter().zip(&lines[1..]).all(|(name, line)| *line == format!("{} = {}", name, first));
+    if let (true, Some(rest)) = (copies, lines[0].strip_prefix(&format!("{} = ", first))) {
+        let names: Vec<&str> = [first].into_iter().chain(fan_out.iter().copied()).map(String::as_str).collect();
+        lines[0] = format!("{} = {}", names.join(", "), rest);
+        lines.drain(1..=fan_out.len());
+    }
+    Ok(lines)
+}
+
+// Rewrites a program or library with one statement per line and uniform spacing. Comments are
+// kept: those on lines of their own stay there, the rest follow the statement they were in.
+pub fn format_source(source: &str) -> Result<String, NandError> {
+    let mut result = Vec::<String>::new();
+    let mut statement = String::new();
+    let mut statement_tokens = Vec::<String>::new();
+    let mut trailing = Vec::<String>::new();
+    let mut paren_count = 0;
+    let mut blank = false;
+
+    for line in source.lines() {
+        // `#define`, `#include` and `@key(value)` annotation lines are kept as written, macro
+        // invocations read as calls
+        if paren_count == 0 && (line.trim_start().starts_with('#') || parse_annotations(line).is_some()) {
+            if blank {
+                result.push(String::new());
+                blank = false;
+            }
+            result.push(line.trim().to_string());
+            continue;
+        }
+        let (comments, tokens): (Vec<String>, Vec<String>) = tokenize_comments(line)?.into_iter().map(|(tok, _)| tok).partition(|tok| tok.starts_with("//"));
+        if tokens.is_empty() && paren_count == 0 {
+            if comments.is_empty() {
+                blank = !result.is_empty();
+                continue;
+            }
+            if blank {
+                result.push(String::new());
+                blank = false;
+            }
+            result.extend(comments);
+            continue;
+        }
+        if blank && paren_count == 0 {
+            result.push(String::new());
+            blank = false;
+        }
+
+        for tok in &tokens {
+            if tok == "(" {
+                paren_count += 1;
+            }
+            else if tok == ")" {
+                paren_count -= 1;
+            }
+        }
+        statement += line.split("//").next().unwrap();
+        statement.push('\n');
+        statement_tokens.extend(tokens.iter().cloned());
+        trailing.extend(comments);
+        if paren_count > 0 {
+            continue;
+        }
+
+        let mut lines = if tokens.len() == 1 && is_chip_header(&tokens[0]) && statement.lines().count() == 1 {
+            vec![tokens[0].clone()]
+        }
+        else {
+            render_statement(&statement, &statement_tokens)?
+        };
+        if let Some(last) = lines.last_mut() {
+            for comment in trailing.drain(..) {
+                *last += &format!(" {}", comment);
+            }
+        }
+        result.extend(lines);
+        result.append(&mut trailing);
+        statement.clear();
+        statement_tokens.clear();
+    }
+    if !statement.trim().is_empty() {
+        result.extend(render_statement(&statement, &statement_tokens)?);
+    }
+    result.extend(trailing);
+
+    Ok(result.into_iter().map(|line| line + "\n").collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fan_out_statements_keep_their_shape() {
+        let source = "T\nINPUTS: a, b\nOUTPUTS: x, y, z\nx,y ,  z = NAND( a,b )\n";
+        assert_eq!(format_source(source).unwrap(), "T\nINPUTS: a, b\nOUTPUTS: x, y, z\nx, y, z = NAND(a, b)\n");
+        let split = "T\nINPUTS: a, b\nx, y = NAND(\n    a,\n    b\n) // both\n";
+        assert_eq!(format_source(split).unwrap(), "T\nINPUTS: a, b\nx, y = NAND(a, b) // both\n");
+        // Copies written out by hand stay as they are
+        let copies = "T\nINPUTS: a\nx = NOT(a)\ny = x\n";
+        assert_eq!(format_source(copies).unwrap(), copies);
+    }
+
+    #[test]
+    fn reduce_is_formatted_as_written() {
+        assert_eq!(format_source("out = REDUCE( XOR,a, b,c )\n").unwrap(), "out = REDUCE(XOR, a, b, c)\n");
+    }
+
+    #[test]
+    fn inline_comments_are_kept() {
+        let source = "// Inverts a\nINV\nINPUTS: a   // the only input\nout=NAND( a,a )  // tied together\n";
+        assert_eq!(format_source(source).unwrap(), "// Inverts a\nINV\nINPUTS: a // the only input\nout = NAND(a, a) // tied together\n");
+    }
+}
@@ -0,0 +1,3030 @@
+use std::{collections::{BTreeMap, HashMap, HashSet, VecDeque}, fmt, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use rayon::prelude::*;
+
+mod analysis;
+mod api;
+mod binary;
+mod chip;
+mod assertions;
+mod debug;
+mod deps;
+mod dir;
+mod format;
+mod macros;
+mod optimize;
+mod probe;
+mod stdlib;
+mod stream;
+mod template;
+mod timing;
+mod validity;
+mod vectors;
+mod visit;
+mod web;
+#[cfg(feature = "watch")]
+mod watch;
+
+pub use analysis::{TableOptions, TruthRow, TruthTable};
+use analysis::TableKey;
+pub use api::{ApiChange, Port};
+pub use assertions::{Assertion, TestReport, TestResult};
+pub use chip::{Chip, ChipError};
+pub use debug::DebugSession;
+use assertions::split_assertions;
+pub use deps::{CycleError, referenced_chips};
+pub use dir::{nand_files, read_library};
+pub use format::format_source;
+pub use macros::expand_macros;
+pub use stdlib::STDLIB;
+pub use template::expand_template;
+pub use vectors::TestVector;
+pub use visit::{TokenVisitor, walk, walk_body};
+pub use web::{evaluate, json_string};
+#[cfg(feature = "watch")]
+pub use watch::ChipWatcher;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Token {
+    Chip(String),
+    ChipIO(String, String), // CHIP_NAME[.CHIP_OUTPUT] - Defaults to the first output
+    Input(String),
+    IO(String, String),
+    Output(String),
+    True,
+    False,
+    Const(u64),
+    Assign,
+    LParen,
+    RParen,
+    Comma,
+    Assert, // ASSERT <expression> == <literal>
+    Inputs(Vec<String>), // INPUTS: declaration
+    Outputs(Vec<String>), // OUTPUTS: declaration
+    DefaultOutput(String), // @default(name) annotation, the output used when no `.out` is given
+    Doc(String), // `///` lines right above a chip's header in a library, without the slashes
+    Attribute(String, String), // @key(value) annotation above a chip's header in a library
+    Bang, // ! prefix, only seen before parse_expressions
+    Not(Box<Token>), // An inverted input binding or expression
+    Expression(Vec<Token>),
+}
+
+// The token as source, see token_source
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", token_source(self))
+    }
+}
+
+
+pub fn tokenize(code: &str) -> Result<Vec<String>, NandError> {
+    Ok(tokenize_lines(code)?.into_iter().map(|(tok, _)| tok).collect())
+}
+
+// Tokens along with the line, counted from 1, each one starts on
+pub fn tokenize_lines(code: &str) -> Result<Vec<(String, usize)>, NandError> {
+    tokenize_with(code, false)
+}
+
+// Like tokenize_lines, but each comment is kept as a token too, `//` included, for tools such as
+// format_source that need to write them back out
+pub fn tokenize_comments(code: &str) -> Result<Vec<(String, usize)>, NandError> {
+    tokenize_with(code, true)
+}
+
+fn tokenize_with(code: &str, keep_comments: bool) -> Result<Vec<(String, usize)>, NandError> {
+    Tokens::new(code.chars(), keep_comments).collect()
+}
+
+// Like tokenize_lines, but driven one character at a time so only the current token is held in
+// memory, for large generated sources read incrementally
+pub fn tokenize_iter<I: IntoIterator<Item = char>>(chars: I) -> Tokens<I::IntoIter> {
+    Tokens::new(chars.into_iter(), false)
+}
+
+// The tokenizer state machine. Yields each token with its line, then an error if the input ended
+// inside a string.
+pub struct Tokens<I: Iterator<Item = char>> {
+    chars: I,
+    keep_comments: bool,
+    line: usize,
+    word_line: usize,
+    current_word: String,
+    is_comment: bool,
+    is_string: bool,
+    // A single character can complete two tokens, as in `a(`
+    ready: VecDeque<(String, usize)>,
+    done: bool,
+}
+
+impl<I: Iterator<Item = char>> Tokens<I> {
+    fn new(chars: I, keep_comments: bool) -> Self {
+        Self { chars, keep_comments, line: 1, word_line: 1, current_word: String::new(), is_comment: false, is_string: false, ready: VecDeque::new(), done: false }
+    }
+
+    // Ends the current word, if there is one
+    fn push_word(&mut self) {
+        if !self.current_word.is_empty() {
+            self.ready.push_back((std::mem::take(&mut self.current_word), self.word_line));
+        }
+    }
+
+    fn feed(&mut self, c: char) {
+        // Carriage returns are dropped everywhere, strings included, so CRLF files tokenize like LF ones
+        if c == '\r' {
+            return;
+        }
+        if self.current_word.is_empty() {
+            self.word_line = self.line;
+        }
+        if c == '\n' {
+            self.line += 1;
+        }
+        if self.is_comment {
+            if c == '\n' {
+                self.is_comment = false;
+                if self.keep_comments {
+                    self.ready.push_back((self.current_word.trim_end().to_string(), self.word_line));
+                    self.current_word.clear();
+                }
+            }
+            else if self.keep_comments {
+                self.current_word.push(c);
+            }
+            return;
+        }
+        // Quoted strings are kept verbatim, quotes included, as a single token
+        if self.is_string {
+            self.current_word.push(c);
+            if c == '"' {
+                self.push_word();
+                self.is_string = false;
+            }
+            return;
+        }
+        if c == '"' {
+            self.push_word();
+            self.current_word.push(c);
+            self.word_line = self.line;
+            self.is_string = true;
+            return;
+        }
+        if c == '\n' {
+            // Words never continue onto the next line
+            self.push_word();
+            return;
+        }
+        if c.is_whitespace() {
+            // Keywords are followed by an expression which would otherwise run into them
+            if self.current_word == "ASSERT" {
+                self.push_word();
+            }
+            return;
+        }
+        if c == '(' || c == ')' || c == '=' || c == ',' || c == '!' {
+            self.push_word();
+            self.ready.push_back((c.into(), self.line));
+            return;
+        }
+        self.current_word.push(c);
+        // A comment can start right after a word, as in `out//note`, which ends the word
+        if let Some(word) = self.current_word.strip_suffix("//") {
+            let word = word.to_string();
+            self.current_word.clear();
+            if !word.is_empty() {
+                self.ready.push_back((word, self.word_line));
+            }
+            self.is_comment = true;
+            if self.keep_comments {
+                self.current_word += "//";
+                self.word_line = self.line;
+            }
+        }
+    }
+}
+
+impl<I: Iterator<Item = char>> Iterator for Tokens<I> {
+    type Item = Result<(String, usize), NandError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(tok) = self.ready.pop_front() {
+                return Some(Ok(tok));
+            }
+            if self.done {
+                return None;
+            }
+            match self.chars.next() {
+                Some(c) => self.feed(c),
+                None => {
+                    self.done = true;
+                    if self.is_string {
+                        return Some(Err(NandError::Tokenize(format!("Unterminated string: {}", self.current_word))));
+                    }
+                    if !self.current_word.is_empty() {
+                        let word = self.current_word.trim_end().to_string();
+                        self.current_word.clear();
+                        self.ready.push_back((word, self.word_line));
+                    }
+                },
+            }
+        }
+    }
+}
+
+// Numeric literals, either decimal or with a 0x/0o/0b prefix. As in Rust, underscores may separate
+// digits, e.g. `0b1010_1010` or `1_000`.
+pub fn parse_literal(tok: &str) -> Option<u64> {
+    let (digits, radix) = if let Some(hex) = tok.strip_prefix("0x") {
+        (hex, 16)
+    }
+    else if let Some(octal) = tok.strip_prefix("0o") {
+        (octal, 8)
+    }
+    else if let Some(bin) = tok.strip_prefix("0b") {
+        (bin, 2)
+    }
+    else if tok.starts_with(|c: char| c.is_ascii_digit()) {
+        (tok, 10)
+    }
+    else {
+        return None;
+    };
+    u64::from_str_radix(&digits.replace('_', ""), radix).ok()
+}
+
+// true/1 and false/0 fill or clear every lane, anything else numeric is a Token::Const
+pub fn lex_literal(tok: &str) -> Option<Token> {
+    if tok.eq_ignore_ascii_case("true") || tok == "1" {
+        return Some(Token::True);
+    }
+    if tok.eq_ignore_ascii_case("false") || tok == "0" {
+        return Some(Token::False);
+    }
+    parse_literal(tok).map(Token::Const)
+}
+
+pub fn lex(tokens: &[String]) -> Result<Vec<Token>, NandError> {
+    lex_lines(tokens, &[])
+}
+
+// `lines` holds the line of each token for error messages, it may be empty
+fn lex_lines(tokens: &[String], lines: &[usize]) -> Result<Vec<Token>, NandError> {
+    let mut result: Vec<Token> = Vec::new();
+    let mut has_output = false;
+    let mut assigning = false;
+    let mut paren_count = 0;
+    // ASSERT statements compare their expression against a literal after '=='
+    let mut asserting = false;
+    let mut comparing = false;
+    let mut compare_count = 0;
+    // Counts statements from 1 so errors can say where they happened
+    let mut statement = 1;
+    // `x, y = ...` assigns the other names from the first once the statement ends
+    let mut out_name = String::new();
+    let mut fan_out = Vec::<String>::new();
+    let mut expecting_name = false;
+
+    for (i, tok) in tokens.iter().enumerate() {
+        if !has_output {
+            if tok == "(" || tok == ")" || tok == "," {
+                return Err(NandError::Lex { statement, line: lines.get(i).copied(), message: format!("Unexpected token: {}, expected output name", tok) });
+            }
+            if tok == "ASSERT" {
+                result.push(Token::Assert);
+                has_output = true;
+                assigning = true;
+                asserting = true;
+                continue;
+            }
+            result.push(Token::Output(tok.into()));
+            out_name = tok.clone();
+            has_output = true;
+        }
+        else if comparing {
+            if tok == "=" && compare_count < 2 {
+                compare_count += 1;
+                if compare_count == 2 {
+                    result.push(Token::Assign);
+                }
+                continue;
+            }
+            match lex_literal(tok) {
+                Some(literal) if compare_count == 2 => result.push(literal),
+                _ => return Err(NandError::Lex { statement, line: lines.get(i).copied(), message: format!("Unexpected token {}, expected '==' followed by a literal", tok) }),
+            }
+            // End of the assertion
+            has_output = false;
+            assigning = false;
+            asserting = false;
+            statement += 1;
+            comparing = false;
+            compare_count = 0;
+        }
+        else if !assigning {
+            // A name alone on its line, like a half-written statement
+            let next_line = i > 0 && lines.get(i).is_some_and(|line| lines.get(i - 1) != Some(line));
+            if let (true, Some(Token::Output(name))) = (tok != "=" && next_line, result.last()) {
+                return Err(NandError::Lex { statement, line: lines.get(i - 1).copied(), message: format!("Output '{}' has no assignment", name) });
+            }
+            if expecting_name {
+                if ["=", "(", ")", ",", "!"].contains(&tok.as_str()) {
+                    return Err(NandError::Lex { statement, line: lines.get(i).copied(), message: format!("Unexpected token {}, expected an output name after ','", tok) });
+                }
+                fan_out.push(tok.clone());
+                expecting_name = false;
+                continue;
+            }
+            if tok == "," {
+                expecting_name = true;
+                continue;
+            }
+            if tok != "=" {
+                return Err(NandError::Lex { statement, line: lines.get(i).copied(), message: format!("Unexpected token {}, expected '='", tok) });
+            }
+            result.push(Token::Assign);
+            assigning = true
+        }
+        else {
+            // RHS
+            if tok == ")" {
+                result.push(Token::RParen);
+                paren_count -= 1;
+                if paren_count == 0 && asserting {
+                    comparing = true;
+                }
+                else if paren_count == 0 {
+                    // End of current statement
+                    assigning = false;
+                    has_output = false;
+                    statement += 1;
+                    assign_fan_out(&mut result, &out_name, &mut fan_out);
+                }
+            }
+            else if tok == "(" {
+                // The previous token which was misidentified as an input is now a chip
+                if match result.last() {
+                    None => true,
+                    Some(Token::Chip(_)) => true,
+                    Some(Token::Input(_)) => false,
+                    Some(Token::Output(_)) => true,
+                    Some(Token::True) => true,
+                    Some(Token::False) => true,
+                    Some(Token::Const(_)) => true,
+                    Some(Token::Assign) => true,
+                    Some(Token::LParen) => true,
+                    Some(Token::RParen) => true,
+                    Some(Token::Comma) => true,
+                    Some(Token::Inputs(_)) => true,
+                    Some(Token::Outputs(_)) => true,
+                    Some(Token::DefaultOutput(_)) => true,
+                    Some(Token::Doc(_)) => true,
+                    Some(Token::Attribute(_, _)) => true,
+                    Some(Token::Bang) => true,
+                    Some(Token::Not(_)) => true,
+                    Some(Token::Assert) => true,
+                    Some(Token::Expression(_)) => true,
+                    Some(Token::IO(_, _)) => true,
+                    Some(Token::ChipIO(_, _)) => true,
+                } {
+                    return Err(NandError::Lex { statement, line: lines.get(i).copied(), message: format!("Unexpected token: {}", tok) });
+                }
+
+                // Can actually convert previous from input to chip now
+                let last_token = result.last().unwrap().clone();
+                if let Token::Input(x) = last_token {
+                    if x.contains(':') {
+                        return Err(NandError::Lex { statement, line: lines.get(i - 1).copied(), message: format!("Binding {} cannot take a call, only a name or literal", x) });
+                    }
+                    // There are no buses to index into, a call can only pick one output
+                    if x.split('.').count() > 2 || x.split('.').any(|part| part.is_empty()) {
+                        return Err(NandError::Lex { statement, line: lines.get(i - 1).copied(), message: format!("Invalid chip name {}, expected CHIP or CHIP.output", x) });
+                    }
+                    // This will always be the case
+                    result.pop();
+                    result.push(Token::Chip(x));
+                    result.push(Token::LParen);
+                }
+                paren_count += 1;
+            }
+            else if tok == "," {
+                result.push(Token::Comma);
+            }
+            else if tok == "!" {
+                result.push(Token::Bang);
+            }
+            else if let Some(literal) = lex_literal(tok) {
+                result.push(literal);
+            }
+            else {
+                // Names cannot start with a digit, so this is a literal with bad or missing digits like
+                // `0x`, on its own or as the source of an `input: source` binding
+                let source = tok.split_once(':').map_or(tok.as_str(), |(_, source)| source);
+                if source.starts_with(|c: char| c.is_ascii_digit()) && lex_literal(source).is_none() {
+                    return Err(NandError::Lex { statement, line: lines.get(i).copied(), message: format!("Invalid literal {}", source) });
+                }
+                // `input: !x` would bind nothing and drop the rest, invert inside the chip or pass by position instead
+                if source.is_empty() {
+                    return Err(NandError::Lex { statement, line: lines.get(i).copied(), message: format!("Binding {} has no source, expected a name or literal", tok) });
+                }
+                result.push(Token::Input(tok.into()));
+            }
+
+            // A statement that is just a literal, or a name not followed by '(', ends right away
+            if paren_count == 0 {
+                let literal = matches!(result.last(), Some(Token::True) | Some(Token::False) | Some(Token::Const(_)));
+                let name = matches!(result.last(), Some(Token::Input(_))) && tokens.get(i + 1).is_none_or(|next| next != "(");
+                if asserting && name {
+                    comparing = true;
+                }
+                else if !asserting && (literal || name) {
+                    assigning = false;
+                    has_output = false;
+                    statement += 1;
+                    assign_fan_out(&mut result, &out_name, &mut fan_out);
+                }
+            }
+        }
+    }
+
+    if paren_count > 0 {
+        return Err(NandError::Lex { statement, line: lines.last().copied(), message: String::from("Missing ')' at the end of the statement") });
+    }
+    if has_output {
+        let message = match result.as_slice() {
+            [.., Token::Output(name)] | [.., Token::Output(name), Token::Assign] => format!("Output '{}' has no assignment", name),
+            _ => String::from("Incomplete statement at the end of the input"),
+        };
+        return Err(NandError::Lex { statement, line: lines.last().copied(), message });
+    }
+
+    Ok(result)
+}
+
+// Each extra name of a `x, y = ...` statement reads the first, so the expression is evaluated once
+fn assign_fan_out(result: &mut Vec<Token>, out_name: &str, fan_out: &mut Vec<String>) {
+    for name in fan_out.drain(..) {
+        result.extend([Token::Output(name), Token::Assign, Token::Input(out_name.to_string())]);
+    }
+}
+
+pub fn lex2(tokens: &[Token]) -> Result<Vec<Token>, NandError> {
+    let mut result = Vec::<Token>::new();
+    let mut current_tokens = vec![];
+    let mut in_assert = false;
+
+    for tok in tokens {
+        if let Token::Output(_) | Token::Assert = tok {
+            if !current_tokens.is_empty() {
+                // Flush current tokens as expression
+                result.push(parse_statement(&current_tokens)?);
+                current_tokens.clear();
+            }
+            in_assert = matches!(tok, Token::Assert);
+            result.push(tok.clone());
+        }
+        else if let Token::Assign = tok {
+            // result.push(tok.clone());
+            // An assertion's expression comes before its '=='
+            if in_assert && !current_tokens.is_empty() {
+                result.push(parse_statement(&current_tokens)?);
+            }
+            // Clear the current tokens
+            current_tokens.clear();
+        }
+        else {
+            // Otherwise add to current tokens
+            if let Token::Chip(x) = tok {
+                if x.contains('.') {
+                    current_tokens.push(Token::ChipIO(x.split('.').next().unwrap().into(), x.split('.').nth(1).unwrap().into()));
+                }
+                else {
+                    current_tokens.push(tok.clone());
+                }
+            }
+            else {
+                current_tokens.push(tok.clone());
+            }
+        }
+    }
+    if !current_tokens.is_empty() {
+        // Flush current tokens as expression
+        result.push(parse_statement(&current_tokens)?);
+    }
+
+    Ok(result)
+}
+
+// The value of a statement, which unlike a call argument cannot bind an input by name
+fn parse_statement(tokens: &[Token]) -> Result<Token, NandError> {
+    match parse_expressions(tokens)? {
+        Token::IO(input, source) => Err(NandError::Parse(format!("Only call arguments can name an input: {}: {}", input, source))),
+        tok => Ok(tok),
+    }
+}
+
+pub fn lex_final(tokens: &[Token]) -> Vec<Token> {
+    let mut result = Vec::<Token>::new();
+    for tok in tokens {
+        match tok {
+            Token::Chip(x) => {
+                if x.contains('.') {
+                    result.push(Token::ChipIO(x.split('.').next().unwrap().into(), x.split('.').nth(1).unwrap().into()));
+                }
+                else {
+                    result.push(tok.clone())
+                }
+            },
+            Token::ChipIO(_, _) => result.push(tok.clone()),
+            Token::Input(_) => result.push(tok.clone()),
+            Token::IO(_, _) => result.push(tok.clone()),
+            Token::Output(_) => result.push(tok.clone()),
+            Token::True => result.push(tok.clone()),
+            Token::False => result.push(tok.clone()),
+            Token::Const(_) => result.push(tok.clone()),
+            Token::Assign => result.push(tok.clone()),
+            Token::LParen => result.push(tok.clone()),
+            Token::RParen => result.push(tok.clone()),
+            Token::Comma => result.push(tok.clone()),
+            Token::Inputs(_) => result.push(tok.clone()),
+            Token::Outputs(_) => result.push(tok.clone()),
+            Token::DefaultOutput(_) => result.push(tok.clone()),
+            Token::Doc(_) => result.push(tok.clone()),
+            Token::Attribute(_, _) => result.push(tok.clone()),
+            Token::Bang => result.push(tok.clone()),
+            Token::Not(_) => result.push(tok.clone()),
+            Token::Assert => result.push(tok.clone()),
+            Token::Expression(_) => result.push(tok.clone()),
+        }
+    }
+    result
+}
+
+// `name: source` binds a named input, a bare `source` is bound by position. Bare names are not
+// read as `source: source` too, `AND(b, a)` has to keep meaning `AND(a: b, b: a)`; when the names
+// line up with the inputs, as in `NAND(a, b)`, the two readings agree anyway.
+fn binding(x: &str) -> Token {
+    match x.split_once(':') {
+        Some((name, source)) => Token::IO(name.into(), source.into()),
+        None => Token::Input(x.into()),
+    }
+}
+
+pub fn parse_expressions(tokens: &[Token]) -> Result<Token, NandError> {
+    // `!x` inverts whatever follows it
+    if let Some(Token::Bang) = tokens.first() {
+        return Ok(Token::Not(Box::new(parse_expressions(&tokens[1..])?)));
+    }
+    // Base cases, we have just an input, or true, or false
+    if tokens.len() == 1 {
+        let tok = tokens.first().unwrap();
+        match tok {
+            Token::Chip(_) => {},
+            Token::Input(x) => return Ok(binding(x)),
+            Token::Output(_) => {},
+            Token::True => return Ok(tok.clone()),
+            Token::False => return Ok(tok.clone()),
+            Token::Const(_) => return Ok(tok.clone()),
+            Token::Assign => {},
+            Token::LParen => {},
+            Token::RParen => {},
+            Token::Comma => {},
+            Token::Assert => {},
+            Token::Inputs(_) => {},
+            Token::Outputs(_) => {},
+            Token::DefaultOutput(_) => {},
+            Token::Doc(_) => {},
+            Token::Attribute(_, _) => {},
+            Token::Bang => {},
+            Token::Not(_) => return Ok(tok.clone()),
+            Token::Expression(_) => return Ok(tok.clone()),
+            Token::IO(_, _) => {},
+            Token::ChipIO(_, _) => {},
+        }
+    }
+    // TODO: Ensure parens match closing
+    // TODO: Check the number of tokens etc
+    let this_chip = match tokens.first() {
+        Some(tok) => tok,
+        None => return Err(NandError::Parse(String::from("Expected an expression"))),
+    };
+    let mut input_expressions: Vec<Token> = vec![this_chip.clone()];
+    let mut p_count = 0;
+    let mut closed = false;
+    let mut current_expression = Vec::<Token>::new();
+
+    for tok in tokens {
+        // Nothing may follow the chip's closing parenthesis
+        if closed {
+            return Err(NandError::Parse(format!("Unexpected token after closing parenthesis: {}", tok)));
+        }
+        if let Token::LParen = tok {
+            p_count += 1;
+            current_expression.push(tok.clone());
+            if p_count == 1 {
+                // Refresh the current expression
+                current_expression.clear();
+            }
+            continue;
+        }
+
+        if let Token::RParen = tok {
+            p_count -= 1;
+            if p_count > 0 {
+                current_expression.push(tok.clone());
+            }
+            if p_count == 0 {
+                // Refresh the current expression, `CHIP()` and a trailing comma have nothing to flush
+                if !current_expression.is_empty() {
+                    input_expressions.push(parse_expressions(&current_expression)?);
+                }
+                current_expression.clear();
+                closed = true;
+            }
+            continue;
+        }
+
+        if p_count == 1 {
+            // We are on the current chip's input level
+            if let Token::Comma = tok {
+                // A trailing comma is fine, a leading or doubled one leaves an argument out
+                if current_expression.is_empty() {
+                    return Err(NandError::Parse(format!("Missing argument before ',' in call to {}", token_source(this_chip))));
+                }
+                // We can flush the current expression
+                input_expressions.push(parse_expressions(&current_expression)?);
+                current_expression.clear();
+                continue;
+            }
+        }
+
+        // Otherwise we can add to the current expression if it is inside the chip's parentheses (p_count > 0)
+        if p_count > 0 {
+            current_expression.push(tok.clone());
+        }
+    }
+
+    if input_expressions.len() == 1 {
+        let tok = input_expressions.first().unwrap();
+        if let Token::Input(x) = tok {
+            return Ok(binding(x));
+        }
+    }
+
+    if let Token::Chip(name) = this_chip {
+        if name == "REDUCE" {
+            reduce(&input_expressions[1..])?;
+        }
+    }
+
+    // We can now return an expression in the form <CHIP, Inputs>
+    Ok(Token::Expression(input_expressions))
+}
+
+// `REDUCE(CHIP, a, b, c)` folds a two input chip over its arguments from the left, giving
+// `CHIP(CHIP(a, b), c)`
+fn reduce(arguments: &[Token]) -> Result<Token, NandError> {
+    let [Token::Input(chip_name), first, rest @ ..] = arguments else {
+        return Err(NandError::Parse(String::from("REDUCE expects a chip name followed by the values to fold")));
+    };
+    if let Some(tok) = arguments.iter().find(|tok| matches!(tok, Token::IO(_, _))) {
+        return Err(NandError::Parse(format!("REDUCE passes its values by position, {} cannot name an input", tok)));
+    }
+    let callee = match chip_name.split_once('.') {
+        Some((chip_name, output)) => Token::ChipIO(chip_name.into(), output.into()),
+        None => Token::Chip(chip_name.clone()),
+    };
+    Ok(rest.iter().fold(first.clone(), |folded, tok| Token::Expression(vec![callee.clone(), folded, tok.clone()])))
+}
+
+// The call a `REDUCE(CHIP, ...)` expression stands for, None for any other call. Parsed code keeps
+// REDUCE as written, so it formats and shows up in errors the way it was typed. That makes REDUCE a
+// reserved name, parse_library rejects a chip called it.
+pub(crate) fn expand_reduce(e_codes: &[Token]) -> Option<Token> {
+    match e_codes.first() {
+        Some(Token::Chip(name)) if name == "REDUCE" => reduce(&e_codes[1..]).ok(),
+        _ => None,
+    }
+}
+
+// Parses a program after expanding its `#define` macros
+pub fn parse(code: &str) -> Result<Vec<Token>, NandError> {
+    parse_statements(&expand_macros(code)?)
+}
+
+// Like parse but leaving macros alone, their invocations read as chip calls
+pub(crate) fn parse_statements(code: &str) -> Result<Vec<Token>, NandError> {
+    // Declarations and annotations sit on lines of their own, blanked out to keep line numbers
+    let mut result = Vec::<Token>::new();
+    let mut statements = String::new();
+    for line in code.lines() {
+        if let Some(declaration) = parse_declaration(line) {
+            result.push(declaration);
+        }
+        else {
+            statements += line;
+        }
+        statements.push('\n');
+    }
+    // A statement runs until its parentheses are balanced again, newlines inside it do not end it
+    let (tokens, lines): (Vec<String>, Vec<usize>) = tokenize_lines(&statements)?.into_iter().unzip();
+    result.extend(lex_final(&lex2(&lex_lines(&tokens, &lines)?)?));
+    Ok(result)
+}
+
+fn is_chip_header(tok: &str) -> bool {
+    tok.chars().all(|c| c.is_alphanumeric() || c == '_') && !tok.starts_with(|c: char| c.is_ascii_digit())
+}
+
+// `INPUTS: a, b`, `OUTPUTS: out` or `@default(out)` lines
+fn parse_declaration(line: &str) -> Option<Token> {
+    let line: String = line.split("//").next().unwrap().chars().filter(|c| !c.is_whitespace()).collect();
+    let names = |list: &str| list.split(',').filter(|x| !x.is_empty()).map(String::from).collect::<Vec<String>>();
+    if let Some(list) = line.strip_prefix("INPUTS:") {
+        return Some(Token::Inputs(names(list)));
+    }
+    if let Some(list) = line.strip_prefix("OUTPUTS:") {
+        return Some(Token::Outputs(names(list)));
+    }
+    if let Some(name) = line.strip_prefix("@default(").and_then(|x| x.strip_suffix(')')) {
+        return Some(Token::DefaultOutput(name.to_string()));
+    }
+    None
+}
+
+// `@key(value)` annotation lines, several to a line as in `@area(12) @vendor("acme")`. Quotes
+// around a value are dropped. @default is a declaration, see parse_declaration.
+fn parse_annotations(line: &str) -> Option<Vec<Token>> {
+    let mut rest = line.trim();
+    if !rest.starts_with('@') || parse_declaration(line).is_some() {
+        return None;
+    }
+    let mut annotations = Vec::<Token>::new();
+    while let Some(annotation) = rest.strip_prefix('@') {
+        let (key, after) = annotation.split_once('(')?;
+        let key = key.trim();
+        if !is_chip_header(key) {
+            return None;
+        }
+        let after = after.trim_start();
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let (value, after) = quoted.split_once('"')?;
+                (value, after.trim_start().strip_prefix(')')?)
+            },
+            None => {
+                let (value, after) = after.split_once(')')?;
+                (value.trim(), after)
+            },
+        };
+        annotations.push(Token::Attribute(key.to_string(), value.to_string()));
+        rest = after.trim_start();
+    }
+    if !rest.is_empty() && !rest.starts_with("//") {
+        return None;
+    }
+    Some(annotations)
+}
+
+// Splits a source file into chips. Each chip starts with a header line holding just its name,
+// optionally followed by INPUTS:/OUTPUTS: lines, then its statements. `///` docs and `@key(value)`
+// annotations above a header belong to that chip. `#include "file"` lines are only understood by
+// load_file and load_dir, which resolve them before parsing. Chips are returned in source order.
+pub fn parse_library(source: &str) -> Result<Vec<(String, Vec<Token>)>, NandError> {
+    let source = &expand_macros(source)?;
+    let mut chips = Vec::<(String, Vec<Token>)>::new();
+    let mut current_chip: Option<String> = None;
+    let mut current_declarations = Vec::<Token>::new();
+    let mut current_body = String::new();
+    let mut paren_count = 0;
+    let mut doc_lines = Vec::<&str>::new();
+    let mut annotations = Vec::<Token>::new();
+
+    // Every line of the source gets a line in the body, even if blank, so errors report source lines
+    for (index, line) in source.lines().enumerate() {
+        if let Some(doc) = line.trim_start().strip_prefix("///").filter(|_| paren_count == 0) {
+            doc_lines.push(doc.strip_prefix(' ').unwrap_or(doc));
+            current_body.push('\n');
+            continue;
+        }
+        if let Some(line_annotations) = parse_annotations(line).filter(|_| paren_count == 0) {
+            annotations.extend(line_annotations);
+            current_body.push('\n');
+            continue;
+        }
+        let tokens = tokenize(line)?;
+        let doc = std::mem::take(&mut doc_lines);
+        if tokens.is_empty() {
+            current_body.push('\n');
+            continue;
+        }
+
+        // Only look for headers between statements, so a statement split over several lines stays intact
+        if paren_count == 0 {
+            if tokens.len() == 1 && is_chip_header(&tokens[0]) {
+                if let Some(chip_name) = current_chip.take() {
+                    current_declarations.extend(parse_statements(&current_body)?);
+                    chips.push((chip_name, current_declarations.clone()));
+                }
+                if tokens[0] == "REDUCE" {
+                    return Err(NandError::Parse(format!("REDUCE is reserved and cannot name a chip, on line {}", index + 1)));
+                }
+                if chips.iter().any(|(chip_name, _)| *chip_name == tokens[0]) {
+                    return Err(NandError::Parse(format!("Chip {} is defined more than once, again on line {}", tokens[0], index + 1)));
+                }
+                current_chip = Some(tokens[0].clone());
+                current_declarations.clear();
+                if !doc.is_empty() {
+                    current_declarations.push(Token::Doc(doc.join("\n")));
+                }
+                current_declarations.append(&mut annotations);
+                current_body = "\n".repeat(index + 1);
+                continue;
+            }
+            if let Some(declaration) = parse_declaration(line) {
+                // Declarations go below the header, above the first chip they would be dropped
+                if current_chip.is_none() {
+                    return Err(NandError::Parse(format!("Declaration {} is not inside a chip", line.trim())));
+                }
+                current_declarations.push(declaration);
+                current_body.push('\n');
+                continue;
+            }
+        }
+
+        if let Some(annotation) = annotations.first() {
+            return Err(NandError::Parse(format!("Annotation {} is not above a chip header", annotation)));
+        }
+        if current_chip.is_none() {
+            return Err(NandError::Parse(format!("Statement outside of a chip: {}", line.trim())));
+        }
+        for tok in &tokens {
+            if tok == "(" {
+                paren_count += 1;
+            }
+            else if tok == ")" {
+                paren_count -= 1;
+            }
+        }
+        current_body += line;
+        current_body.push('\n');
+    }
+    if let Some(annotation) = annotations.first() {
+        return Err(NandError::Parse(format!("Annotation {} is not above a chip header", annotation)));
+    }
+    if let Some(chip_name) = current_chip {
+        current_declarations.extend(parse_statements(&current_body)?);
+        chips.push((chip_name, current_declarations));
+    }
+
+    Ok(chips)
+}
+
+pub fn declared_inputs(code: &[Token]) -> Option<Vec<String>> {
+    code.iter().find_map(|tok| match tok {
+        Token::Inputs(names) => Some(names.clone()),
+        _ => None,
+    })
+}
+
+pub fn declared_outputs(code: &[Token]) -> Option<Vec<String>> {
+    code.iter().find_map(|tok| match tok {
+        Token::Outputs(names) => Some(names.clone()),
+        _ => None,
+    })
+}
+
+// Output names in the order the statements assign them
+pub fn statement_outputs(code: &[Token]) -> Vec<String> {
+    let mut names = Vec::<String>::new();
+    for tok in code {
+        if let Token::Output(name) = tok {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+    }
+    names
+}
+
+// Whether an argument or expression reads `name` anywhere inside it
+fn reads_name(tok: &Token, name: &str) -> bool {
+    struct Reads<'a> {
+        name: &'a str,
+        found: bool,
+    }
+    impl TokenVisitor for Reads<'_> {
+        fn visit_io(&mut self, _input: &str, source: &str) {
+            self.found |= source == self.name;
+        }
+        fn visit_input(&mut self, source: &str) {
+            self.found |= source == self.name;
+        }
+    }
+
+    let mut reads = Reads { name, found: false };
+    walk(tok, &mut reads);
+    reads.found
+}
+
+// Writes a token back out as source, e.g. `ADDER.sum(a: x, !b)`
+pub fn token_source(tok: &Token) -> String {
+    match tok {
+        Token::Chip(chip_name) => chip_name.clone(),
+        Token::ChipIO(chip_name, output) => format!("{}.{}", chip_name, output),
+        Token::Input(source) => source.clone(),
+        Token::IO(input, source) => format!("{}: {}", input, source),
+        Token::Output(name) => name.clone(),
+        Token::True => String::from("1"),
+        Token::False => String::from("0"),
+        Token::Const(value) => value.to_string(),
+        Token::Assign => String::from("="),
+        Token::LParen => String::from("("),
+        Token::RParen => String::from(")"),
+        Token::Comma => String::from(","),
+        Token::Assert => String::from("ASSERT"),
+        Token::Inputs(names) => format!("INPUTS: {}", names.join(", ")),
+        Token::Outputs(names) => format!("OUTPUTS: {}", names.join(", ")),
+        Token::DefaultOutput(name) => format!("@default({})", name),
+        Token::Doc(text) => text.lines().map(|line| format!("/// {}", line)).collect::<Vec<String>>().join("\n"),
+        Token::Attribute(key, value) if !value.is_empty() && value.chars().all(|c| c.is_alphanumeric() || c == '_') => format!("@{}({})", key, value),
+        Token::Attribute(key, value) => format!("@{}(\"{}\")", key, value),
+        Token::Bang => String::from("!"),
+        Token::Not(inner) => format!("!{}", token_source(inner)),
+        Token::Expression(e_codes) => {
+            let arguments: Vec<String> = e_codes.iter().skip(1).map(token_source).collect();
+            format!("{}({})", e_codes.first().map(token_source).unwrap_or_default(), arguments.join(", "))
+        },
+    }
+}
+
+// Every name read, in order, that is not a literal, a local or already listed
+struct Sources {
+    locals: HashSet<String>,
+    result: Vec<String>,
+}
+
+impl Sources {
+    fn read(&mut self, source: &str) {
+        if lex_literal(source).is_none() && !self.locals.contains(source) && !self.result.iter().any(|x| x == source) {
+            self.result.push(source.to_string());
+        }
+    }
+}
+
+impl TokenVisitor for Sources {
+    fn visit_io(&mut self, _input: &str, source: &str) {
+        self.read(source);
+    }
+    fn visit_input(&mut self, source: &str) {
+        self.read(source);
+    }
+}
+
+// The names a chip body reads in the order they first appear, leaving out literals and names
+// assigned by an earlier statement. These are its inputs when it declares none.
+pub(crate) fn inferred_inputs(code: &[Token]) -> Vec<String> {
+    let mut sources = Sources { locals: HashSet::new(), result: vec![] };
+    let mut assigning: Option<&String> = None;
+    for tok in code {
+        if let Token::Output(out) = tok {
+            assigning = Some(out);
+            continue;
+        }
+        walk(tok, &mut sources);
+        if let Some(out) = assigning.take() {
+            sources.locals.insert(out.clone());
+        }
+    }
+    sources.result
+}
+
+// The output with the lexicographically smallest name, so the choice does not depend on the
+// map's order. 0 when there are none, as for a chip with an empty body.
+pub fn get_first_output(out: &HashMap<String, u8>) -> u8 {
+    out.iter().min_by(|(a, _), (b, _)| a.cmp(b)).map(|(_, value)| *value).unwrap_or(0)
+}
+
+// The output a chip call resolves to when no `.out` is given: the @default annotation,
+// else the first declared output, else the first statement's output
+fn default_output(code: &[Token]) -> String {
+    for tok in code {
+        if let Token::DefaultOutput(out) = tok {
+            return out.clone();
+        }
+    }
+    if let Some(out) = declared_outputs(code).and_then(|outputs| outputs.first().cloned()) {
+        return out;
+    }
+    for tok in code {
+        if let Token::Output(out) = tok {
+            return out.clone();
+        }
+    }
+    String::from("out")
+}
+
+// Name given to the `index`th positional input: a, b, ..., z, aa, ab, ...
+pub fn input_param_name(index: usize) -> String {
+    let mut name = Vec::<u8>::new();
+    let mut remaining = index + 1;
+    while remaining > 0 {
+        remaining -= 1;
+        name.push(b'a' + (remaining % 26) as u8);
+        remaining /= 26;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap()
+}
+
+pub trait Executable {
+    fn eval(&self, code: Vec<Token>, inputs: &HashMap<String, u8>) -> Result<HashMap<String, u8>, EvalError>;
+}
+
+#[derive(Debug, Clone)]
+pub enum Signal {
+    Input(String),
+    Const(u8),
+    Gate(usize),
+    Call(usize), // An output of a sub-chip that was kept separate instead of inlined
+}
+
+// A single NAND gate in a flattened netlist
+#[derive(Debug, Clone)]
+pub struct Gate {
+    pub name: String, // <instance>_n<index>, e.g. XOR_NOT0_n0 for the first gate of XOR's first NOT
+    pub a: Signal,
+    pub b: Signal,
+}
+
+// One output of a sub-chip too large to inline, evaluated through its own netlist
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub name: String, // Instance name, as the sub-chip's gates would have been prefixed
+    pub chip: String,
+    pub output: String,
+    pub inputs: Vec<(String, Signal)>,
+    pub after: usize, // Number of gates before the call, none of the later ones feed into it
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FlattenOptions {
+    pub inline_threshold: Option<usize>, // Sub-chips with more gates than this become calls
+}
+
+// A chip expanded down to NAND gates, and calls to sub-chips when an inline threshold is set.
+// Gates and calls only ever refer to earlier gates and calls.
+#[derive(Debug, Clone, Default)]
+pub struct Netlist {
+    pub gates: Vec<Gate>,
+    pub calls: Vec<Call>,
+    pub subchips: BTreeMap<String, Netlist>, // Netlists of the chips named by calls, by name so dumps are stable
+    pub outputs: Vec<(String, Signal)>,
+    pub probes: Vec<(String, Signal)>, // Outputs of every inlined sub-chip instance, as <instance>.<output>
+}
+
+impl Netlist {
+    pub fn signal_name(&self, signal: &Signal) -> String {
+        match signal {
+            Signal::Input(name) => name.clone(),
+            Signal::Const(x) => format!("{:#04x}", x),
+            Signal::Gate(i) => self.gates[*i].name.clone(),
+            Signal::Call(i) => format!("{}.{}", self.calls[*i].name, self.calls[*i].output),
+        }
+    }
+
+    pub(crate) fn value_of(signal: &Signal, inputs: &HashMap<String, u8>, values: &[u8], calls: &[u8]) -> u8 {
+        match signal {
+            Signal::Input(name) => *inputs.get(name).unwrap_or(&0),
+            Signal::Const(x) => *x,
+            Signal::Gate(i) => values[*i],
+            Signal::Call(i) => calls[*i],
+        }
+    }
+
+    fn call_value(&self, call: &Call, inputs: &HashMap<String, u8>, values: &[u8], calls: &[u8]) -> u8 {
+        let call_inputs: HashMap<String, u8> = call.inputs.iter().map(|(name, signal)| (name.clone(), Netlist::value_of(signal, inputs, values, calls))).collect();
+        *self.subchips[&call.chip].eval(&call_inputs).get(&call.output).unwrap_or(&0)
+    }
+
+    // Every call's value given the values of the gates, which are not affected by them in turn
+    pub(crate) fn call_values(&self, inputs: &HashMap<String, u8>, values: &[u8]) -> Vec<u8> {
+        let mut calls = Vec::<u8>::with_capacity(self.calls.len());
+        for call in &self.calls {
+            let value = self.call_value(call, inputs, values, &calls);
+            calls.push(value);
+        }
+        calls
+    }
+
+    // The settled values of every gate and every call
+    pub(crate) fn settle(&self, inputs: &HashMap<String, u8>) -> (Vec<u8>, Vec<u8>) {
+        let mut values = Vec::<u8>::with_capacity(self.gates.len());
+        let mut calls = Vec::<u8>::with_capacity(self.calls.len());
+        for i in 0..=self.gates.len() {
+            while calls.len() < self.calls.len() && self.calls[calls.len()].after == i {
+                let value = self.call_value(&self.calls[calls.len()], inputs, &values, &calls);
+                calls.push(value);
+            }
+            if let Some(gate) = self.gates.get(i) {
+                let a = Netlist::value_of(&gate.a, inputs, &values, &calls);
+                let b = Netlist::value_of(&gate.b, inputs, &values, &calls);
+                values.push(!(a & b));
+            }
+        }
+        (values, calls)
+    }
+
+    // The settled value of every gate
+    pub fn gate_values(&self, inputs: &HashMap<String, u8>) -> Vec<u8> {
+        self.settle(inputs).0
+    }
+
+    pub fn eval(&self, inputs: &HashMap<String, u8>) -> HashMap<String, u8> {
+        let (values, calls) = self.settle(inputs);
+
+        let mut output = HashMap::<String, u8>::new();
+        for (name, signal) in &self.outputs {
+            output.insert(name.clone(), Netlist::value_of(signal, inputs, &values, &calls));
+        }
+        output
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownChip(pub String);
+
+impl fmt::Display for UnknownChip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown chip: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownChip {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateChip(pub String);
+
+impl fmt::Display for DuplicateChip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Chip {} is already loaded", self.0)
+    }
+}
+
+impl std::error::Error for DuplicateChip {}
+
+// Bookkeeping while expanding a chip. Gates and sub-chip instances are numbered per
+// instance in traversal order, so the same chip always flattens to the same names.
+#[derive(Default)]
+struct FlattenState {
+    gates: Vec<Gate>,
+    calls: Vec<Call>,
+    subchips: BTreeMap<String, Netlist>,
+    probes: Vec<(String, Signal)>,
+    locals: Vec<HashMap<String, Signal>>, // Statement outputs of each chip body being expanded
+    shared: Vec<HashMap<String, (Token, Signal)>>, // Expressions already expanded in each body, by their tokens
+    options: FlattenOptions,
+    used: HashSet<String>,
+    gate_counts: HashMap<String, usize>,
+    instance_counts: HashMap<String, usize>,
+}
+
+impl FlattenState {
+    fn add_gate(&mut self, instance: &str, a: Signal, b: Signal) -> Signal {
+        let index = self.gate_counts.entry(instance.to_string()).or_insert(0);
+        self.gates.push(Gate { name: format!("{}_n{}", instance, index), a, b });
+        *index += 1;
+        Signal::Gate(self.gates.len() - 1)
+    }
+
+    fn add_instance(&mut self, instance: &str, chip_name: &str) -> String {
+        let index = self.instance_counts.entry(instance.to_string()).or_insert(0);
+        let name = format!("{}_{}{}", instance, chip_name, index);
+        *index += 1;
+        name
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    UnknownChip(String),
+    DepthExceeded { chip: String, max_depth: usize },
+    UnknownSignal(String),
+    WrongArity { chip: String, expected: usize, given: usize },
+    UnknownInput { chip: String, input: String },
+    LiteralOverflow { value: u64, width: u32 }, // Only under LiteralMode::Error
+    CannotFlattenNative(String), // A native chip reached while flattening, only the tree walk can run it
+    Cycle(CycleError), // Chips calling themselves through other chips, which cannot be flattened
+    // Another error along with the statement it happened in, and the chip holding that statement
+    // when known. Only the innermost statement is recorded.
+    InStatement { chip: Option<String>, statement: String, error: Box<EvalError> },
+}
+
+impl EvalError {
+    // The error without any statement it was wrapped in
+    pub fn root_cause(&self) -> &EvalError {
+        match self {
+            EvalError::InStatement { error, .. } => error.root_cause(),
+            e => e,
+        }
+    }
+
+    fn in_statement(self, out_name: &str, tok: &Token) -> EvalError {
+        match self {
+            EvalError::InStatement { .. } => self,
+            e => EvalError::InStatement { chip: None, statement: format!("{} = {}", out_name, token_source(tok)), error: Box::new(e) },
+        }
+    }
+
+    fn in_chip(self, chip_name: &str) -> EvalError {
+        match self {
+            EvalError::InStatement { chip: None, statement, error } => EvalError::InStatement { chip: Some(chip_name.to_string()), statement, error },
+            e => e,
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::UnknownChip(chip) => write!(f, "Unknown chip: {}", chip),
+            EvalError::DepthExceeded { chip, max_depth } => write!(f, "Chip {} nested deeper than the maximum depth of {}", chip, max_depth),
+            EvalError::UnknownSignal(path) => write!(f, "Unknown signal: {}", path),
+            EvalError::WrongArity { chip, expected, given } => write!(f, "{} takes {} inputs but {} are given", chip, expected, given),
+            EvalError::UnknownInput { chip, input } => write!(f, "{} has no input {}", chip, input),
+            EvalError::LiteralOverflow { value, width } => write!(f, "Literal {} does not fit in {} bits", value, width),
+            EvalError::CannotFlattenNative(chip) => write!(f, "Native chip {} cannot be flattened", chip),
+            EvalError::Cycle(cycle) => write!(f, "{}", cycle),
+            EvalError::InStatement { chip: Some(chip), statement, error } => write!(f, "{} in chip {} at '{}'", error, chip, statement),
+            EvalError::InStatement { chip: None, statement, error } => write!(f, "{} at '{}'", error, statement),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EvalError::InStatement { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+// Any failure, tagged with the stage it happened in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NandError {
+    Tokenize(String),
+    Lex { statement: usize, line: Option<usize>, message: String }, // Statements and lines are counted from 1
+    Parse(String),
+    Eval(EvalError),
+}
+
+impl fmt::Display for NandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NandError::Tokenize(message) => write!(f, "Syntax error. {}", message),
+            NandError::Lex { statement, line: Some(line), message } => write!(f, "Syntax error in statement {} (line {}). {}", statement, line, message),
+            NandError::Lex { statement, line: None, message } => write!(f, "Syntax error in statement {}. {}", statement, message),
+            NandError::Parse(message) => write!(f, "Syntax error. {}", message),
+            NandError::Eval(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for NandError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NandError::Eval(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<EvalError> for NandError {
+    fn from(e: EvalError) -> Self {
+        NandError::Eval(e)
+    }
+}
+
+// How a numeric literal wider than the configured width is materialized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiteralMode {
+    Wrap,
+    Saturate,
+    Error,
+}
+
+// How a result is shown, always limited to the lanes within the width
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueFormat {
+    Binary,  // 0b00000101
+    Hex,     // 0x05
+    Decimal, // 5
+    Signed,  // -3, the top lane within the width being the sign bit
+    PerLane, // 0:1 1:0 2:1 3:0 4:0 5:0 6:0 7:0, lane 0 first
+}
+
+impl ValueFormat {
+    // Widths outside 1 to 8 bits are clamped into that range
+    pub fn format(&self, value: u8, width: u32) -> String {
+        let width = width.clamp(1, 8);
+        let value = value & ((1u16 << width) - 1) as u8;
+        let width = width as usize;
+        match self {
+            ValueFormat::Binary => format!("{:#0digits$b}", value, digits = width + 2),
+            ValueFormat::Hex => format!("{:#0digits$x}", value, digits = width.div_ceil(4) + 2),
+            ValueFormat::Decimal => value.to_string(),
+            ValueFormat::Signed => as_signed(value, width as u32).to_string(),
+            ValueFormat::PerLane => (0..width).map(|lane| format!("{}:{}", lane, (value >> lane) & 1)).collect::<Vec<String>>().join(" "),
+        }
+    }
+}
+
+// Reads the low `width` bits of a value as a two's complement number, clamping the width to 1 to 8 bits
+pub fn as_signed(value: u8, width: u32) -> i64 {
+    let width = width.clamp(1, 8);
+    let value = (value & ((1u16 << width) - 1) as u8) as i64;
+    if value >> (width - 1) & 1 == 1 {
+        value - (1 << width)
+    }
+    else {
+        value
+    }
+}
+
+// A chip implemented in Rust, taking and returning named values like NAND
+pub type NativeChip = Box<dyn Fn(&HashMap<String, u8>) -> HashMap<String, u8> + Send + Sync>;
+
+// Called with a signal's name and value each time eval assigns it
+pub type ProbeSink = Box<dyn Fn(&str, u8) + Send + Sync>;
+
+// Time spent evaluating each chip, from eval_profiled
+pub type Profile = HashMap<String, Duration>;
+
+pub struct ChipEvaluator {
+    chips: HashMap<String, Vec<Token>>,
+    // Names of the loaded chips in the order they were first loaded
+    load_order: Vec<String>,
+    // Flattened netlists along with every chip name they were expanded from
+    flat_cache: Mutex<HashMap<String, (Netlist, HashSet<String>)>>,
+    // Truth tables by the chip code and inputs swept, so identical chips share one, along with every
+    // chip name the code depends on
+    table_cache: Mutex<HashMap<TableKey, (TruthTable, HashSet<String>)>>,
+    width: u32,
+    literal_mode: LiteralMode,
+    assertions: Vec<Assertion>,
+    // Shared with the evaluators from clone_with_overrides
+    natives: HashMap<String, Arc<NativeChip>>,
+    max_depth: usize,
+    primitive: String,
+    templates: HashMap<String, String>,
+    sinks: HashMap<String, Vec<ProbeSink>>,
+}
+
+impl Default for ChipEvaluator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChipEvaluator {
+    pub fn new() -> Self {
+        Self {chips: HashMap::new(), load_order: Vec::new(), flat_cache: Mutex::new(HashMap::new()), table_cache: Mutex::new(HashMap::new()), width: 8, literal_mode: LiteralMode::Wrap, assertions: Vec::new(), natives: HashMap::new(), max_depth: 128, primitive: String::from("NAND"), templates: HashMap::new(), sinks: HashMap::new()}
+    }
+
+    // Number of bit lanes a value holds, at most 8
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn set_width(&mut self, width: u32) {
+        if width == 0 || width > 8 {
+            panic!("Unsupported width {}, expected 1 to 8 bits", width);
+        }
+        self.width = width;
+        // Constants are baked into flattened netlists
+        self.flat_cache.lock().unwrap().clear();
+        self.table_cache.lock().unwrap().clear();
+    }
+
+    pub fn mask(&self) -> u8 {
+        ((1u16 << self.width) - 1) as u8
+    }
+
+    pub fn literal_mode(&self) -> LiteralMode {
+        self.literal_mode
+    }
+
+    pub fn set_literal_mode(&mut self, mode: LiteralMode) {
+        self.literal_mode = mode;
+        self.flat_cache.lock().unwrap().clear();
+        self.table_cache.lock().unwrap().clear();
+    }
+
+    // The value of a literal token, None for other tokens: true fills every lane, numeric literals
+    // follow the literal mode
+    pub fn literal(&self, tok: &Token) -> Result<Option<u8>, EvalError> {
+        Ok(match tok {
+            Token::True => Some(self.mask()),
+            Token::False => Some(0),
+            Token::Const(value) => {
+                let mask = self.mask() as u64;
+                if *value <= mask {
+                    return Ok(Some(*value as u8));
+                }
+                match self.literal_mode {
+                    LiteralMode::Wrap => Some((value & mask) as u8),
+                    LiteralMode::Saturate => Some(mask as u8),
+                    LiteralMode::Error => return Err(EvalError::LiteralOverflow { value: *value, width: self.width }),
+                }
+            },
+            _ => None,
+        })
+    }
+
+    pub fn load_chip(&mut self, chip_name: &str, code: &[Token])  {
+        let (code, assertions) = split_assertions(chip_name, code);
+        self.assertions.retain(|assertion| assertion.chip != chip_name);
+        self.assertions.extend(assertions);
+        if self.chips.insert(chip_name.to_string(), code).is_none() {
+            self.load_order.push(chip_name.to_string());
+        }
+        self.invalidate(chip_name);
+    }
+
+    // Like load_chip, but refuses to replace a chip that is already loaded
+    pub fn load_chip_checked(&mut self, chip_name: &str, code: &[Token]) -> Result<(), DuplicateChip> {
+        if self.chips.contains_key(chip_name) {
+            return Err(DuplicateChip(chip_name.to_string()));
+        }
+        self.load_chip(chip_name, code);
+        Ok(())
+    }
+
+    // How deep eval may nest calls, sub-chips and arguments that are calls alike, before giving up
+    // with EvalError::DepthExceeded. The default of 128 stays well within the 2 MB stack of a
+    // spawned thread in a debug build, which overflows at around 350.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    // The name of the built-in gate every chip is made of, NAND unless renamed
+    pub fn primitive(&self) -> &str {
+        &self.primitive
+    }
+
+    pub fn set_primitive(&mut self, name: &str) {
+        self.primitive = name.to_string();
+        self.flat_cache.lock().unwrap().clear();
+        self.table_cache.lock().unwrap().clear();
+    }
+
+    pub fn contains_chip(&self, chip_name: &str) -> bool {
+        self.chips.contains_key(chip_name)
+    }
+
+    // Every loaded chip in the order it was first loaded, reloading a chip keeps its place
+    pub fn chip_names(&self) -> &[String] {
+        &self.load_order
+    }
+
+    // Unloads a chip along with its assertions. Chips still calling it fail with UnknownChip.
+    pub fn remove_chip(&mut self, chip_name: &str) -> Option<Vec<Token>> {
+        let code = self.chips.remove(chip_name)?;
+        self.load_order.retain(|name| name != chip_name);
+        self.assertions.retain(|assertion| assertion.chip != chip_name);
+        self.invalidate(chip_name);
+        Some(code)
+    }
+
+    // Calls `sink` whenever eval assigns a statement named `signal_name`, in any chip
+    pub fn add_probe_sink(&mut self, signal_name: &str, sink: ProbeSink) {
+        self.sinks.entry(signal_name.to_string()).or_default().push(sink);
+    }
+
+    pub fn clear_probe_sinks(&mut self) {
+        self.sinks.clear();
+    }
+
+    // A call without `.name` reads the native's `out` output, or the output with the smallest name
+    // when it has none
+    pub fn register_native(&mut self, chip_name: &str, chip: NativeChip) {
+        self.natives.insert(chip_name.to_string(), Arc::new(chip));
+        self.invalidate(chip_name);
+    }
+
+    // A copy of the evaluator with the given chips loaded over its own, leaving this one untouched.
+    // Native chips are shared, probe sinks are left behind.
+    pub fn clone_with_overrides(&self, overrides: HashMap<String, Vec<Token>>) -> ChipEvaluator {
+        let mut clone = ChipEvaluator {
+            chips: self.chips.clone(),
+            load_order: self.load_order.clone(),
+            flat_cache: Mutex::new(self.flat_cache.lock().unwrap().clone()),
+            table_cache: Mutex::new(self.table_cache.lock().unwrap().clone()),
+            width: self.width,
+            literal_mode: self.literal_mode,
+            assertions: self.assertions.clone(),
+            natives: self.natives.clone(),
+            max_depth: self.max_depth,
+            primitive: self.primitive.clone(),
+            templates: self.templates.clone(),
+            sinks: HashMap::new(),
+        };
+        // Chips new to the clone are loaded in name order, as load_library does
+        let mut overrides: Vec<(String, Vec<Token>)> = overrides.into_iter().collect();
+        overrides.sort_by(|a, b| a.0.cmp(&b.0));
+        for (chip_name, code) in &overrides {
+            clone.load_chip(chip_name, code);
+        }
+        clone
+    }
+
+    // Makes `new_name` resolve to the same body as an already loaded chip
+    pub fn alias(&mut self, new_name: &str, existing_name: &str) -> Result<(), UnknownChip> {
+        let code = match self.chips.get(existing_name) {
+            Some(code) => code.clone(),
+            None => return Err(UnknownChip(existing_name.to_string())),
+        };
+        if self.chips.insert(new_name.to_string(), code).is_none() {
+            self.load_order.push(new_name.to_string());
+        }
+        self.invalidate(new_name);
+        Ok(())
+    }
+
+    // The `///` comment written above the chip's header, one line per comment line
+    pub fn doc_of(&self, chip_name: &str) -> Option<String> {
+        self.chips.get(chip_name)?.iter().find_map(|tok| match tok {
+            Token::Doc(text) => Some(text.clone()),
+            _ => None,
+        })
+    }
+
+    // The `@key(value)` annotations above the chip's header. Empty for chips that are not loaded.
+    pub fn attributes_of(&self, chip_name: &str) -> HashMap<String, String> {
+        let Some(code) = self.chips.get(chip_name) else {
+            return HashMap::new();
+        };
+        code.iter().filter_map(|tok| match tok {
+            Token::Attribute(key, value) => Some((key.clone(), value.clone())),
+            _ => None,
+        }).collect()
+    }
+
+    // The chip's output names in order: its OUTPUTS declaration, else every statement output.
+    // Empty for chips that are not loaded.
+    pub fn outputs_of(&self, chip_name: &str) -> Vec<String> {
+        match self.chips.get(chip_name) {
+            Some(code) => declared_outputs(code).unwrap_or_else(|| statement_outputs(code)),
+            None => vec![],
+        }
+    }
+
+    // The chip's input names in order: its INPUTS declaration, else the names it reads in the order
+    // they first appear, leaving out literals and names assigned by an earlier statement.
+    // Empty for chips that are not loaded.
+    pub fn inputs_of(&self, chip_name: &str) -> Vec<String> {
+        let code = match self.chips.get(chip_name) {
+            Some(code) => code,
+            None => return vec![],
+        };
+        declared_inputs(code).unwrap_or_else(|| inferred_inputs(code))
+    }
+
+    // Declared outputs that no statement of the chip assigns
+    pub fn check_outputs(&self, chip_name: &str) -> Result<Vec<String>, UnknownChip> {
+        let code = match self.chips.get(chip_name) {
+            Some(code) => code,
+            None => return Err(UnknownChip(chip_name.to_string())),
+        };
+        let mut undriven = declared_outputs(code).unwrap_or_default();
+        undriven.retain(|name| !code.iter().any(|tok| matches!(tok, Token::Output(out) if out == name)));
+        Ok(undriven)
+    }
+
+    // Statement outputs that are neither declared outputs nor read by a later statement.
+    // Without an OUTPUTS declaration every statement is an output, so nothing is reported.
+    pub fn unused_locals(&self, chip_name: &str) -> Result<Vec<String>, UnknownChip> {
+        let code = match self.chips.get(chip_name) {
+            Some(code) => code,
+            None => return Err(UnknownChip(chip_name.to_string())),
+        };
+        let outputs = match declared_outputs(code) {
+            Some(outputs) => outputs,
+            None => return Ok(vec![]),
+        };
+
+        let mut unused = Vec::<String>::new();
+        for (i, tok) in code.iter().enumerate() {
+            if let Token::Output(name) = tok {
+                let read_later = code.iter().skip(i + 2).any(|later| reads_name(later, name));
+                if !outputs.contains(name) && !read_later && !unused.contains(name) {
+                    unused.push(name.clone());
+                }
+            }
+        }
+        Ok(unused)
+    }
+
+    // Inputs that a statement of the chip also assigns. Statements before the assignment read the
+    // input, the ones after read the assigned value, and callers only ever see the assigned value.
+    pub fn shadowed_inputs(&self, chip_name: &str) -> Result<Vec<String>, UnknownChip> {
+        let code = match self.chips.get(chip_name) {
+            Some(code) => code,
+            None => return Err(UnknownChip(chip_name.to_string())),
+        };
+        let mut shadowed = self.inputs_of(chip_name);
+        shadowed.retain(|name| code.iter().any(|tok| matches!(tok, Token::Output(out) if out == name)));
+        Ok(shadowed)
+    }
+
+    // Calls that do not fit the chip they call: more or fewer arguments than declared inputs, bindings to
+    // inputs it does not declare, or outputs it does not have. Unloaded and native chips are skipped.
+    pub fn check_calls(&self, chip_name: &str) -> Result<Vec<String>, UnknownChip> {
+        let code = match self.chips.get(chip_name) {
+            Some(code) => code,
+            None => return Err(UnknownChip(chip_name.to_string())),
+        };
+        let mut errors = Vec::<String>::new();
+        for tok in code {
+            self.check_call(chip_name, tok, &mut errors);
+        }
+        Ok(errors)
+    }
+
+    fn check_call(&self, chip_name: &str, tok: &Token, errors: &mut Vec<String>) {
+        let e_codes = match tok {
+            Token::Expression(e_codes) => e_codes,
+            Token::Not(inner) => return self.check_call(chip_name, inner, errors),
+            _ => return,
+        };
+        if let Some(folded) = expand_reduce(e_codes) {
+            return self.check_call(chip_name, &folded, errors);
+        }
+        let (callee, output) = match e_codes.first() {
+            Some(Token::Chip(callee)) => (callee, None),
+            Some(Token::ChipIO(callee, output)) => (callee, Some(output)),
+            _ => return,
+        };
+        let (inputs, outputs) = if *callee == self.primitive {
+            (vec![String::from("a"), String::from("b")], vec![String::from("out")])
+        }
+        else if let Some(code) = self.chips.get(callee) {
+            (declared_inputs(code).unwrap_or_default(), self.outputs_of(callee))
+        }
+        else {
+            (vec![], vec![])
+        };
+
+        let arguments = &e_codes[1..];
+        // Chips without an INPUTS declaration take any inputs
+        if !inputs.is_empty() {
+            if arguments.len() != inputs.len() {
+                errors.push(format!("{}: {} takes {} inputs but {} are given", chip_name, callee, inputs.len(), arguments.len()));
+            }
+            for argument in arguments {
+                if let Token::IO(name, _) = argument {
+                    if !inputs.contains(name) {
+                        errors.push(format!("{}: {} has no input {}", chip_name, callee, name));
+                    }
+                }
+            }
+        }
+        if let Some(output) = output {
+            if !outputs.is_empty() && !outputs.contains(output) {
+                errors.push(format!("{}: {} has no output {}", chip_name, callee, output));
+            }
+        }
+        for argument in arguments {
+            self.check_call(chip_name, argument, errors);
+        }
+    }
+
+    // Warnings for every loaded chip, in chip name order
+    pub fn check(&self) -> Vec<String> {
+        let mut chip_names: Vec<&String> = self.chips.keys().collect();
+        chip_names.sort();
+
+        let mut warnings = Vec::<String>::new();
+        for chip_name in chip_names {
+            for name in self.check_outputs(chip_name).unwrap_or_default() {
+                warnings.push(format!("{}: output {} is never assigned", chip_name, name));
+            }
+            for name in self.unused_locals(chip_name).unwrap_or_default() {
+                warnings.push(format!("{}: {} is assigned but never read", chip_name, name));
+            }
+            for name in self.shadowed_inputs(chip_name).unwrap_or_default() {
+                warnings.push(format!("{}: input {} is also assigned, statements after the assignment read the assigned value", chip_name, name));
+            }
+        }
+        warnings
+    }
+
+    // Loads every chip of a multi-chip source file, in source order
+    pub fn load_library(&mut self, source: &str) -> Result<(), NandError> {
+        for (chip_name, code) in parse_library(source)? {
+            self.load_chip(&chip_name, &code);
+        }
+        Ok(())
+    }
+
+    // Drop the cached netlist of this chip and of every chip that was expanded through it
+    fn invalidate(&self, chip_name: &str) {
+        self.flat_cache.lock().unwrap().retain(|_, (_, used)| !used.contains(chip_name));
+        self.table_cache.lock().unwrap().retain(|_, (_, used)| !used.contains(chip_name));
+    }
+
+    pub fn is_flattened(&self, chip_name: &str) -> bool {
+        self.flat_cache.lock().unwrap().contains_key(chip_name)
+    }
+
+    pub fn flatten(&self, chip_name: &str) -> Result<Netlist, EvalError> {
+        self.flatten_with(chip_name, FlattenOptions::default())
+    }
+
+    // Only fully inlined netlists are cached
+    pub fn flatten_with(&self, chip_name: &str, options: FlattenOptions) -> Result<Netlist, EvalError> {
+        let cached = options == FlattenOptions::default();
+        if cached {
+            if let Some((netlist, _)) = self.flat_cache.lock().unwrap().get(chip_name) {
+                return Ok(netlist.clone());
+            }
+        }
+
+        self.check_flattenable(chip_name)?;
+        let mut state = FlattenState { options, ..Default::default() };
+        state.used.insert(chip_name.to_string());
+        let outputs = self.flatten_body(&self.chips[chip_name], None, chip_name, &mut state).map_err(|e| e.in_chip(chip_name))?;
+        let netlist = Netlist { gates: state.gates, calls: state.calls, subchips: state.subchips, outputs, probes: state.probes };
+
+        if cached {
+            self.flat_cache.lock().unwrap().insert(chip_name.to_string(), (netlist.clone(), state.used));
+        }
+        Ok(netlist)
+    }
+
+    // The netlist of the chip, or None when it calls native or missing chips. Only the tree walk can
+    // run those, and it reports missing chips along with the statement calling them.
+    pub(crate) fn flatten_for_eval(&self, chip_name: &str) -> Result<Option<Netlist>, EvalError> {
+        match self.flatten(chip_name) {
+            Ok(netlist) => Ok(Some(netlist)),
+            Err(EvalError::CannotFlattenNative(_) | EvalError::UnknownChip(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn gate_count(&self, chip_name: &str) -> Result<usize, EvalError> {
+        Ok(self.flatten(chip_name)?.gates.len())
+    }
+
+    // Expands a chip body into gates. `bindings` is None for the top level chip, whose inputs stay named
+    fn flatten_body(&self, code: &[Token], bindings: Option<&HashMap<String, Signal>>, instance: &str, state: &mut FlattenState) -> Result<Vec<(String, Signal)>, EvalError> {
+        let mut outputs = Vec::<(String, Signal)>::new();
+        let mut current_out_name = String::from("out");
+        state.locals.push(HashMap::new());
+        state.shared.push(HashMap::new());
+        for tok in code {
+            let signal = match tok {
+                Token::Output(out) => {
+                    current_out_name = out.clone();
+                    continue;
+                },
+                Token::True | Token::False | Token::Const(_) | Token::Expression(_) | Token::Not(_) | Token::Input(_) => match self.flatten_argument(tok, bindings, instance, state) {
+                    Ok(argument) => argument.unwrap().1,
+                    Err(e) => return Err(e.in_statement(&current_out_name, tok)),
+                },
+                _ => continue,
+            };
+            state.locals.last_mut().unwrap().insert(current_out_name.clone(), signal.clone());
+            // Expressions reading the name now see a different signal
+            state.shared.last_mut().unwrap().retain(|_, (shared_tok, _)| !reads_name(shared_tok, &current_out_name));
+            outputs.push((current_out_name.clone(), signal));
+        }
+        state.locals.pop();
+        state.shared.pop();
+        Ok(outputs)
+    }
+
+    // The signal driving one argument of a call, named when it is an IO binding
+    fn flatten_argument(&self, tok: &Token, bindings: Option<&HashMap<String, Signal>>, instance: &str, state: &mut FlattenState) -> Result<Option<(Option<String>, Signal)>, EvalError> {
+        Ok(match tok {
+            Token::IO(x, y) => Some((Some(x.clone()), self.flatten_source(y, bindings, state)?)),
+            Token::Input(y) => Some((None, self.flatten_source(y, bindings, state)?)),
+            Token::Expression(i_toks) => {
+                // Identical expressions within a body share one set of gates
+                let key = format!("{:?}", tok);
+                if let Some((_, signal)) = state.shared.last().and_then(|shared| shared.get(&key)) {
+                    return Ok(Some((None, signal.clone())));
+                }
+                let signal = self.flatten_expression(i_toks, bindings, instance, state)?;
+                if let Some(shared) = state.shared.last_mut() {
+                    shared.insert(key, (tok.clone(), signal.clone()));
+                }
+                Some((None, signal))
+            },
+            Token::Not(inner) => self.flatten_argument(inner, bindings, instance, state)?.map(|(name, signal)| (name, state.add_gate(instance, signal.clone(), signal))),
+            tok => self.literal(tok)?.map(|value| (None, Signal::Const(value))),
+        })
+    }
+
+    // The signal a binding source reads
+    fn flatten_source(&self, y: &str, bindings: Option<&HashMap<String, Signal>>, state: &FlattenState) -> Result<Signal, EvalError> {
+        // Earlier statements of the same body shadow its inputs
+        let local = state.locals.last().and_then(|locals| locals.get(y)).cloned();
+        Ok(match (lex_literal(y), local, bindings) {
+            (Some(literal), _, _) => Signal::Const(self.literal(&literal)?.unwrap()),
+            (None, Some(local), _) => local,
+            (None, None, Some(bound)) => bound.get(y).cloned().unwrap_or(Signal::Const(0)),
+            (None, None, None) => Signal::Input(y.to_string()),
+        })
+    }
+
+    fn flatten_expression(&self, e_codes: &[Token], bindings: Option<&HashMap<String, Signal>>, instance: &str, state: &mut FlattenState) -> Result<Signal, EvalError> {
+        if let Some(folded) = expand_reduce(e_codes) {
+            return Ok(self.flatten_argument(&folded, bindings, instance, state)?.map(|(_, signal)| signal).unwrap_or(Signal::Const(0)));
+        }
+        let mut ec_iter = e_codes.iter();
+        let e_chip = ec_iter.next().unwrap();
+        let (chip_name, chip_out) = match e_chip {
+            Token::Chip(chip_name) => (chip_name, None),
+            Token::ChipIO(chip_name, chip_out) => (chip_name, Some(chip_out)),
+            _ => panic!("Expected a chip, found {}", e_chip),
+        };
+
+        let mut e_inputs = HashMap::<String, Signal>::new();
+        for (i, input_token) in ec_iter.enumerate() {
+            if let Some((name, signal)) = self.flatten_argument(input_token, bindings, instance, state)? {
+                e_inputs.insert(name.unwrap_or_else(|| self.param_name(chip_name, i)), signal);
+            }
+        }
+
+        if *chip_name == self.primitive {
+            check_primitive_call(chip_name, e_codes, e_inputs.keys())?;
+            let a = e_inputs.remove("a").unwrap_or(Signal::Const(0));
+            let b = e_inputs.remove("b").unwrap_or(Signal::Const(0));
+            return Ok(state.add_gate(instance, a, b));
+        }
+
+        // check_flattenable already turned away native and missing chips, this only guards code
+        // reached some other way
+        if self.natives.contains_key(chip_name) {
+            return Err(EvalError::CannotFlattenNative(chip_name.clone()));
+        }
+        state.used.insert(chip_name.clone());
+        let chip_instructions = self.chips.get(chip_name).ok_or_else(|| EvalError::UnknownChip(chip_name.clone()))?;
+        let wanted = chip_out.cloned().unwrap_or_else(|| default_output(chip_instructions));
+        let sub_instance = state.add_instance(instance, chip_name);
+
+        // Large sub-chips are evaluated through their own netlist instead of being copied in
+        if let Some(threshold) = state.options.inline_threshold {
+            if self.gate_count(chip_name)? > threshold {
+                if !state.subchips.contains_key(chip_name) {
+                    let netlist = self.flatten_with(chip_name, state.options)?;
+                    state.subchips.insert(chip_name.clone(), netlist);
+                }
+                let mut inputs: Vec<(String, Signal)> = e_inputs.into_iter().collect();
+                inputs.sort_by(|a, b| a.0.cmp(&b.0));
+                state.calls.push(Call { name: sub_instance, chip: chip_name.clone(), output: wanted, inputs, after: state.gates.len() });
+                return Ok(Signal::Call(state.calls.len() - 1));
+            }
+        }
+
+        let outputs = self.flatten_body(chip_instructions, Some(&e_inputs), &sub_instance, state).map_err(|e| e.in_chip(chip_name))?;
+        for (name, signal) in &outputs {
+            state.probes.push((format!("{}.{}", sub_instance, name), signal.clone()));
+        }
+        Ok(outputs.into_iter().find(|(name, _)| *name == wanted).map(|(_, signal)| signal).unwrap_or(Signal::Const(0)))
+    }
+
+    // Evaluates each chip with the same inputs, in parallel since eval never mutates the evaluator
+    pub fn eval_all(&self, chip_names: &[&str], inputs: &HashMap<String, u8>) -> Result<HashMap<String, HashMap<String, u8>>, EvalError> {
+        chip_names.par_iter().map(|chip_name| {
+            let code = self.chips.get(*chip_name).ok_or_else(|| EvalError::UnknownChip(chip_name.to_string()))?;
+            Ok((chip_name.to_string(), self.eval_body(code, inputs, 0, &mut None).map_err(|e| e.in_chip(chip_name))?))
+        }).collect()
+    }
+
+    // Runs one independent case per bit lane: each case's inputs are packed into its own lane, so
+    // up to `width` cases share a single eval. Longer slices take one eval per `width` cases.
+    pub fn eval_lanes(&self, chip_name: &str, per_lane_inputs: &[HashMap<String, bool>]) -> Result<Vec<HashMap<String, bool>>, EvalError> {
+        let code = self.chips.get(chip_name).ok_or_else(|| EvalError::UnknownChip(chip_name.to_string()))?;
+        let outputs = self.outputs_of(chip_name);
+        let mut results = Vec::<HashMap<String, bool>>::with_capacity(per_lane_inputs.len());
+
+        for cases in per_lane_inputs.chunks(self.width as usize) {
+            let mut inputs = HashMap::<String, u8>::new();
+            for (lane, case) in cases.iter().enumerate() {
+                for (name, value) in case {
+                    *inputs.entry(name.clone()).or_insert(0) |= (*value as u8) << lane;
+                }
+            }
+
+            let result = self.eval_body(code, &inputs, 0, &mut None).map_err(|e| e.in_chip(chip_name))?;
+            for lane in 0..cases.len() {
+                results.push(outputs.iter().map(|name| (name.clone(), (result.get(name).unwrap_or(&0) >> lane) & 1 == 1)).collect());
+            }
+        }
+        Ok(results)
+    }
+
+    // Single-bit evaluation: true fills every lane and each output reads its lowest lane
+    pub fn eval_bools(&self, chip_name: &str, inputs: &HashMap<String, bool>) -> Result<HashMap<String, bool>, EvalError> {
+        let code = self.chips.get(chip_name).ok_or_else(|| EvalError::UnknownChip(chip_name.to_string()))?;
+        let inputs: HashMap<String, u8> = inputs.iter().map(|(name, value)| (name.clone(), if *value { self.mask() } else { 0 })).collect();
+        let result = self.eval_body(code, &inputs, 0, &mut None).map_err(|e| e.in_chip(chip_name))?;
+        Ok(self.outputs_of(chip_name).into_iter().map(|name| {
+            let value = result.get(&name).unwrap_or(&0) & 1 == 1;
+            (name, value)
+        }).collect())
+    }
+
+    // Evaluates a single expression such as `ADDER.carry(a: 1, b: x)`, rather than a whole program
+    pub fn eval_expr(&self, expr_source: &str, inputs: &HashMap<String, u8>) -> Result<u8, NandError> {
+        let code = parse(&format!("out = {}", expr_source))?;
+        match code.get(1) {
+            Some(tok) => Ok(self.eval_argument(tok, inputs, 0, &mut None)?.map(|(_, value)| value).unwrap_or(0)),
+            None => Ok(0),
+        }
+    }
+
+    // The value of one argument of a call, named when it is an IO binding
+    fn eval_argument(&self, tok: &Token, inputs: &HashMap<String, u8>, depth: usize, profile: &mut Option<Profile>) -> Result<Option<(Option<String>, u8)>, EvalError> {
+        Ok(match tok {
+            // Handle IO
+            Token::IO(x, y) => {
+                let value = match lex_literal(y) {
+                    Some(literal) => self.literal(&literal)?.unwrap(),
+                    None => *inputs.get(y).unwrap_or(&0),
+                };
+                Some((Some(x.clone()), value))
+            },
+            // Handle positional inputs
+            Token::Input(y) => Some((None, *inputs.get(y).unwrap_or(&0))),
+            // Handle expressions as inputs to current expression, which nest as deep as a sub-chip does
+            Token::Expression(i_toks) => Some((None, self.eval_expression(i_toks, inputs, depth + 1, profile)?)),
+            // Handle inverted inputs
+            Token::Not(inner) => self.eval_argument(inner, inputs, depth, profile)?.map(|(name, value)| (name, !value)),
+            // Handle literals
+            tok => self.literal(tok)?.map(|value| (None, value)),
+        })
+    }
+
+    // Evaluates a `<CHIP, Inputs>` expression and returns the selected (or default) output
+    // Every argument is evaluated on its own, so a call repeated within the arguments, as in
+    // NAND(ADD.sum(a, b), ADD.sum(a, b)), runs twice with the same result. flatten shares them.
+    fn eval_expression(&self, e_codes: &[Token], inputs: &HashMap<String, u8>, depth: usize, profile: &mut Option<Profile>) -> Result<u8, EvalError> {
+        if let Some(folded) = expand_reduce(e_codes) {
+            return Ok(self.eval_argument(&folded, inputs, depth, profile)?.map(|(_, value)| value).unwrap_or(0));
+        }
+        let mut ec_iter = e_codes.iter();
+        let e_chip =  ec_iter.next().unwrap();
+        let callee = match e_chip {
+            Token::Chip(chip_name) | Token::ChipIO(chip_name, _) => chip_name.as_str(),
+            _ => "",
+        };
+        if depth >= self.max_depth {
+            return Err(EvalError::DepthExceeded { chip: callee.to_string(), max_depth: self.max_depth });
+        }
+        let mut e_inputs = HashMap::<String,u8>::new();
+        for (i, input_token) in ec_iter.enumerate() {
+            // IO bindings are named, everything else is named by position
+            if let Some((name, value)) = self.eval_argument(input_token, inputs, depth, profile)? {
+                e_inputs.insert(name.unwrap_or_else(|| self.param_name(callee, i)), value);
+            }
+        }
+        if callee == self.primitive {
+            check_primitive_call(callee, e_codes, e_inputs.keys())?;
+        }
+
+        // Handle normal CHIPs
+        Ok(if let Token::Chip(chip_name) = e_chip {
+            // Handle NAND CHIP
+            if *chip_name == self.primitive {
+                get_first_output(&nand(&e_inputs))
+            }
+            // Handle native chips, preferring an `out` output, else the one get_first_output picks. They are
+            // looked up before chips loaded from source, so a native chip replaces a loaded one of the same name.
+            else if let Some(native) = self.natives.get(chip_name) {
+                let e_result = native(&e_inputs);
+                match e_result.get("out") {
+                    Some(value) => *value,
+                    None => get_first_output(&e_result),
+                }
+            }
+            // Handle other chips
+            else {
+                let chip_instructions = self.sub_chip(chip_name, depth)?;
+                *self.eval_sub_chip(chip_name, chip_instructions, &e_inputs, depth + 1, profile)?.get(&default_output(chip_instructions)).unwrap_or(&0)
+            }
+        }
+        // Handle CHIPIO chips
+        else if let Token::ChipIO(chip_name, chip_out) = e_chip {
+            // Handle NAND CHIP
+            if *chip_name == self.primitive {
+                *nand(&e_inputs).get(chip_out).unwrap_or(&0)
+            }
+            // Handle native chips
+            else if let Some(native) = self.natives.get(chip_name) {
+                *native(&e_inputs).get(chip_out).unwrap_or(&0)
+            }
+            // Handle other chips
+            else {
+                let chip_instructions = self.sub_chip(chip_name, depth)?;
+                *self.eval_sub_chip(chip_name, chip_instructions, &e_inputs, depth + 1, profile)?.get(chip_out).unwrap_or(&0)
+            }
+        }
+        else {
+            panic!("Expected a chip, found {}", e_chip);
+        })
+    }
+
+    // The input a positional argument binds to: the chip's declared INPUTS in order, else a, b, c, ...
+    fn param_name(&self, chip_name: &str, index: usize) -> String {
+        let declared = self.chips.get(chip_name).and_then(|code| code.iter().find_map(|tok| match tok {
+            Token::Inputs(names) => names.get(index).cloned(),
+            _ => None,
+        }));
+        declared.unwrap_or_else(|| input_param_name(index))
+    }
+
+    // Runs a loaded chip's body, adding the time taken to its entry in the profile when there is one
+    fn eval_sub_chip(&self, chip_name: &str, code: &[Token], inputs: &HashMap<String, u8>, depth: usize, profile: &mut Option<Profile>) -> Result<HashMap<String, u8>, EvalError> {
+        if profile.is_none() {
+            return self.eval_body(code, inputs, depth, profile).map_err(|e| e.in_chip(chip_name));
+        }
+        let start = Instant::now();
+        let result = self.eval_body(code, inputs, depth, profile).map_err(|e| e.in_chip(chip_name));
+        if let Some(profile) = profile {
+            *profile.entry(chip_name.to_string()).or_default() += start.elapsed();
+        }
+        result
+    }
+
+    // Evaluates a chip, also returning the total time spent in each chip it ran, itself included.
+    // A chip's time includes the chips it calls.
+    pub fn eval_profiled(&self, chip_name: &str, inputs: &HashMap<String, u8>) -> Result<(HashMap<String, u8>, Profile), EvalError> {
+        let code = self.chips.get(chip_name).ok_or_else(|| EvalError::UnknownChip(chip_name.to_string()))?;
+        let mut profile = Some(Profile::new());
+        let result = self.eval_sub_chip(chip_name, code, inputs, 0, &mut profile)?;
+        Ok((result, profile.unwrap_or_default()))
+    }
+
+    // Looks up a chip called from `depth` levels deep, refusing to go past max_depth
+    fn sub_chip(&self, chip_name: &str, depth: usize) -> Result<&Vec<Token>, EvalError> {
+        if depth >= self.max_depth {
+            return Err(EvalError::DepthExceeded { chip: chip_name.to_string(), max_depth: self.max_depth });
+        }
+        self.chips.get(chip_name).ok_or_else(|| EvalError::UnknownChip(chip_name.to_string()))
+    }
+
+    fn eval_body(&self, code: &[Token], inputs: &HashMap<String, u8>, depth: usize, profile: &mut Option<Profile>) -> Result<HashMap<String, u8>, EvalError> {
+        let mut output = HashMap::<String, u8>::new();
+        // Earlier statements can be read by later ones like inputs
+        let mut scope = inputs.clone();
+
+        let mut token_iter = code.iter();
+        let mut current_token = token_iter.next();
+        let mut current_out_name: String = String::new();
+        while let Some(tok) = current_token {
+            // The value of a statement ending with this token
+            let value = match tok {
+                Token::Chip(_) => None,
+                Token::ChipIO(_, _) => None,
+                // A bare name copies an input or earlier statement
+                Token::Input(_) => self.eval_argument(tok, &scope, depth, profile).map_err(|e| e.in_statement(statement_name(&current_out_name), tok))?.map(|(_, value)| value),
+                // parse_statement never gives one, code built by hand reads its source like a call argument
+                Token::IO(_, _) => self.eval_argument(tok, &scope, depth, profile).map_err(|e| e.in_statement(statement_name(&current_out_name), tok))?.map(|(_, value)| value),
+                Token::Output(out) => {
+                    current_out_name = out.clone();
+                    None
+                },
+                Token::True | Token::False | Token::Const(_) => self.literal(tok).map_err(|e| e.in_statement(statement_name(&current_out_name), tok))?,
+                Token::Assign => None,
+                Token::LParen => None,
+                Token::RParen => None,
+                Token::Comma => None,
+                Token::Inputs(_) => None,
+                Token::Outputs(_) => None,
+                Token::DefaultOutput(_) => None,
+                Token::Doc(_) => None,
+                Token::Attribute(_, _) => None,
+                Token::Bang => None,
+                Token::Not(_) => self.eval_argument(tok, &scope, depth, profile).map_err(|e| e.in_statement(statement_name(&current_out_name), tok))?.map(|(_, value)| value),
+                Token::Assert => {
+                    // Assertions are only run by run_tests, skip the expression and expected value
+                    token_iter.next();
+                    token_iter.next();
+                    None
+                },
+                Token::Expression(e_codes) => Some(self.eval_expression(e_codes, &scope, depth, profile).map_err(|e| e.in_statement(statement_name(&current_out_name), tok))?),
+            };
+
+            if let Some(value) = value {
+                let name = statement_name(&current_out_name).to_string();
+                if let Some(sinks) = self.sinks.get(&name) {
+                    for sink in sinks {
+                        sink(&name, value);
+                    }
+                }
+                output.insert(name.clone(), value);
+                scope.insert(name, value);
+                current_out_name.clear();
+            }
+
+            current_token = token_iter.next();
+        }
+
+        Ok(output)
+    }
+}
+
+// The name a statement assigns, `out` when it has none
+fn statement_name(out_name: &str) -> &str {
+    if out_name.is_empty() { "out" } else { out_name }
+}
+
+// The primitive always takes exactly a and b, a missing one would silently read as 0
+fn check_primitive_call<'a>(primitive: &str, e_codes: &[Token], mut names: impl Iterator<Item = &'a String>) -> Result<(), EvalError> {
+    if e_codes.len() != 3 {
+        return Err(EvalError::WrongArity { chip: primitive.to_string(), expected: 2, given: e_codes.len() - 1 });
+    }
+    match names.find(|name| *name != "a" && *name != "b") {
+        Some(input) => Err(EvalError::UnknownInput { chip: primitive.to_string(), input: input.clone() }),
+        None => Ok(()),
+    }
+}
+
+pub fn nand(inputs: &HashMap<String, u8>) ->  HashMap<String,u8> {
+    let mut output: HashMap<String,u8> = HashMap::new();
+    let result = !(*inputs.get("a").unwrap_or(&0) & *inputs.get("b").unwrap_or(&0));
+    output.insert(String::from("out"), result);
+    output
+}
+
+impl Executable for ChipEvaluator {
+    fn eval(&self, code: Vec<Token>, inputs: &HashMap<String, u8>) -> Result<HashMap<String, u8>, EvalError> {
+        self.eval_body(&code, inputs, 0, &mut None)
+    }
+}
+
+/*
+A statement ends once its parentheses are balanced, so it can be split over several lines:
+    Y1 = CHIP_A(
+        I1: X1,
+        I2: X2
+    )
+
+CHIP_A
+INPUTS: I1, I2, ..., IN
+OUTPUTS: O1, O2, ..., ON
+
+/// CHIP_B's documentation, returned by doc_of
+CHIP_B
+INPUTS: X1, X2, ..., XN
+OUTPUTS: Y1, Y2, ..., YN
+@default(Y2)
+
+CHIP_B Example Def: Y1 = CHIP_A.O2(I1:X1, I2:X2, ...)
+
+Several outputs can take the same value in one statement: Y1, Y3 = CHIP_A(I1:X1, I2:X2)
+
+Chips without inputs, such as constants, are called with empty parentheses: Y4 = ZERO()
+
+REDUCE folds a two input chip over any number of values, from the left, when it is evaluated:
+    Y5 = REDUCE(CHIP_A, X1, X2, X3) runs as Y5 = CHIP_A(CHIP_A(X1, X2), X3)
+
+Text macros are expanded before parsing, in the lines after their definition:
+    #define INV(x) NAND(x, x)
+    Y6 = INV(X1)
+
+Templates are instantiated at a size N, repeating every line or declared name using {i}:
+    INPUTS: a{i}, b{i}
+    OUTPUTS: out{i}
+    out{i} = AND(a{i}, b{i})
+
+There are no buses: the bits of a value are lanes, independent evaluations of the same chip,
+so there is no bit selection or concatenation such as {a, b}. A wide value is a group of
+single-bit signals, like the a{i} of a template, and joining two groups is just listing both.
+
+Chips are purely combinational. There are no flip-flops or clocked steps, so nothing carries
+state from one eval to the next.
+*/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statements_cannot_bind_an_input_by_name() {
+        assert!(matches!(parse("out = x:a"), Err(NandError::Parse(_))));
+        let cpu = ChipEvaluator::new();
+        let inputs = HashMap::from([(String::from("x"), 0b1100)]);
+        assert_eq!(cpu.eval(parse("out = NAND(a: x, b: 1)").unwrap(), &inputs).unwrap()["out"], 0b11110011);
+        // Hand-built code reads the source like a call argument would
+        let code = vec![Token::Output(String::from("out")), Token::Assign, Token::IO(String::from("a"), String::from("x"))];
+        assert_eq!(cpu.eval(code, &inputs).unwrap()["out"], 0b1100);
+    }
+
+    #[test]
+    fn flatten_reports_chips_it_cannot_expand() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("T\nINPUTS: a\nout = MISSING(a)\n\nA\nINPUTS: a\nout = B(a)\n\nB\nINPUTS: a\nout = NOT(A(a))\n").unwrap();
+        assert_eq!(cpu.flatten("T").unwrap_err(), EvalError::UnknownChip(String::from("MISSING")));
+        assert_eq!(cpu.gate_count("T"), Err(EvalError::UnknownChip(String::from("MISSING"))));
+        let cycle = CycleError { chips: vec![String::from("A"), String::from("B"), String::from("A")] };
+        assert_eq!(cpu.flatten("A").unwrap_err(), EvalError::Cycle(cycle));
+
+        cpu.register_native("MISSING", copy_native());
+        assert_eq!(cpu.flatten("T").unwrap_err(), EvalError::CannotFlattenNative(String::from("MISSING")));
+    }
+
+    #[test]
+    fn flatten_checks_primitive_calls_like_eval() {
+        let mut cpu = ChipEvaluator::new();
+        cpu.load_library("ONE\nINPUTS: a\nout = NAND(a)\n\nNAMED\nINPUTS: a\nout = NAND(a: a, c: a)\n").unwrap();
+        let arity = EvalError::WrongArity { chip: String::from("NAND"), expected: 2, given: 1 };
+        assert!(matches!(cpu.flatten("ONE").unwrap_err(), EvalError::InStatement { error, .. } if *error == arity));
+        assert!(matches!(cpu.flatten("NAMED").unwrap_err(), EvalError::InStatement { error, .. } if matches!(*error, EvalError::UnknownInput { .. })));
+    }
+
+    #[test]
+    fn overflowing_literals_are_an_error_in_error_mode() {
+        let mut cpu = ChipEvaluator::new();
+        cpu.set_width(4);
+        cpu.set_literal_mode(LiteralMode::Error);
+        let overflow = EvalError::LiteralOverflow { value: 16, width: 4 };
+        assert_eq!(cpu.literal(&Token::Const(16)), Err(overflow.clone()));
+        assert_eq!(cpu.literal(&Token::Const(15)), Ok(Some(15)));
+        for source in ["out = 16", "out = NAND(a, 16)", "out = NAND(a: 0x10, b: 1)"] {
+            let error = cpu.eval(parse(source).unwrap(), &HashMap::new()).unwrap_err();
+            assert!(matches!(&error, EvalError::InStatement { error, .. } if **error == overflow), "{}: {}", source, error);
+        }
+
+        cpu.load_library("T\nINPUTS: a\nout = NAND(a, 16)\n").unwrap();
+        assert!(matches!(cpu.flatten("T").unwrap_err(), EvalError::InStatement { error, .. } if *error == overflow));
+        cpu.set_literal_mode(LiteralMode::Wrap);
+        assert_eq!(cpu.literal(&Token::Const(16)), Ok(Some(0)));
+    }
+
+    #[test]
+    fn reduce_is_kept_as_written_and_expanded_when_run() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("R\nINPUTS: a, b, c\nout = REDUCE(XOR, a, b, !c)\n\nX\nINPUTS: a, b, c\nout = XOR(XOR(a, b), !c)\n").unwrap();
+        assert_eq!(token_source(&cpu.chips["R"][2]), "REDUCE(XOR, a, b, !c)");
+        let inputs = HashMap::from([(String::from("a"), 0b1100), (String::from("b"), 0b1010), (String::from("c"), 0b0110)]);
+        assert_eq!(cpu.eval(cpu.chips["R"].clone(), &inputs), cpu.eval(cpu.chips["X"].clone(), &inputs));
+        assert_eq!(cpu.flatten("R").unwrap().eval(&inputs), cpu.flatten("X").unwrap().eval(&inputs));
+        assert_eq!(referenced_chips(&cpu.chips["R"]), vec![String::from("XOR")]);
+        assert_eq!(cpu.used_inputs("R"), HashSet::from([String::from("a"), String::from("b"), String::from("c")]));
+        assert_eq!(cpu.check_calls("R").unwrap(), Vec::<String>::new());
+        assert_eq!(cpu.resolve(), Ok(()));
+        assert_eq!(cpu.check(), Vec::<String>::new());
+
+        let error = cpu.eval(parse("out = REDUCE(MISSING, a, b)").unwrap(), &inputs).unwrap_err();
+        assert_eq!(error.to_string(), "Unknown chip: MISSING at 'out = REDUCE(MISSING, a, b)'");
+        assert!(matches!(parse("out = REDUCE(XOR)"), Err(NandError::Parse(_))));
+    }
+
+
+    #[test]
+    fn chips_cannot_be_named_reduce() {
+        let error = parse_library("REDUCE\nINPUTS: a, b\nout = NAND(a, b)\n").unwrap_err();
+        assert!(matches!(&error, NandError::Parse(message) if message == "REDUCE is reserved and cannot name a chip, on line 1"), "{:?}", error);
+        let mut cpu = ChipEvaluator::with_stdlib();
+        assert!(cpu.load_library("T\nINPUTS: a\nout = a\n\nREDUCE\nINPUTS: a, b\nout = NAND(a, b)\n").is_err());
+        assert!(!cpu.chip_names().iter().any(|chip_name| chip_name == "T" || chip_name == "REDUCE"));
+    }
+    #[test]
+    fn profiles_are_kept_per_call() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("T\nINPUTS: a, b\nout = XOR(a, b)\n").unwrap();
+        let inputs = HashMap::from([(String::from("a"), 0b1100), (String::from("b"), 0b1010)]);
+        let cpu = Arc::new(cpu);
+        let threads: Vec<_> = (0..4).map(|_| {
+            let cpu = cpu.clone();
+            let inputs = inputs.clone();
+            std::thread::spawn(move || (0..50).map(|_| cpu.eval_profiled("T", &inputs).unwrap()).collect::<Vec<_>>())
+        }).collect();
+        for thread in threads {
+            for (outputs, profile) in thread.join().unwrap() {
+                assert_eq!(outputs["out"], 0b0110);
+                assert_eq!(profile.keys().cloned().collect::<HashSet<String>>(), HashSet::from([String::from("T"), String::from("XOR")]));
+            }
+        }
+    }
+
+    #[test]
+    fn literals_without_digits_are_lex_errors() {
+        for source in ["out = 0x", "out = NAND(a, 0b)", "out = NAND(a: 0o, b: 1)", "out = 0xfg"] {
+            assert!(matches!(parse(source), Err(NandError::Lex { message, .. }) if message.starts_with("Invalid literal")), "{}", source);
+        }
+        assert_eq!(parse("out = NAND(a: 0x0f, b: 0b1)").unwrap()[1], Token::Expression(vec![Token::Chip(String::from("NAND")), Token::IO(String::from("a"), String::from("0x0f")), Token::IO(String::from("b"), String::from("0b1"))]));
+    }
+
+    // A native chip passing its `a` input through, for tests of what each pass does with chips it cannot flatten
+    pub(crate) fn copy_native() -> NativeChip {
+        Box::new(|inputs| HashMap::from([(String::from("out"), inputs["a"])]))
+    }
+
+    // The innermost error, past the statements and chips it happened in
+    fn innermost(error: EvalError) -> EvalError {
+        match error {
+            EvalError::InStatement { error, .. } => innermost(*error),
+            error => error,
+        }
+    }
+
+    #[test]
+    fn nesting_past_max_depth_is_an_error() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("ONE\nINPUTS: a\nout = NOT(a)\n\nTWO\nINPUTS: a\nout = ONE(a)\n\nTHREE\nINPUTS: a\nout = TWO(a)\n").unwrap();
+        // THREE, TWO, ONE, NOT and NAND each nest one deeper
+        cpu.set_max_depth(4);
+        assert_eq!(cpu.eval(parse("out = THREE(a)").unwrap(), &HashMap::new()).map_err(innermost), Err(EvalError::DepthExceeded { chip: String::from("NAND"), max_depth: 4 }));
+        // Arguments that are calls nest too
+        assert_eq!(cpu.eval(parse("out = NOT(NOT(NOT(NOT(a))))").unwrap(), &HashMap::new()).map_err(innermost), Err(EvalError::DepthExceeded { chip: String::from("NAND"), max_depth: 4 }));
+        assert_eq!(cpu.eval(parse("out = NOT(NOT(NOT(a)))").unwrap(), &HashMap::new()).unwrap()["out"], 255);
+        cpu.set_max_depth(5);
+        assert_eq!(cpu.eval(parse("out = THREE(a)").unwrap(), &HashMap::new()).unwrap()["out"], 255);
+    }
+
+    #[test]
+    fn cyclic_chips_stop_at_the_default_depth_on_a_small_stack() {
+        // Tests and rayon workers run on 2 MB stacks, as do threads spawned without a size
+        let result = std::thread::Builder::new().stack_size(2 << 20).spawn(|| {
+            let mut cpu = ChipEvaluator::with_stdlib();
+            cpu.load_library("A\nINPUTS: a\nout = AND(OR(XOR(NOT(B(a)), a), a), a)\n\nB\nINPUTS: a\nout = OR(AND(NOT(XOR(A(a), a)), a), a)\n\nC\nINPUTS: a\nout = D(a)\n\nD\nINPUTS: a\nout = C(a)\n").unwrap();
+            ["A", "C"].map(|chip| cpu.eval(parse(&format!("out = {}(a)", chip)).unwrap(), &HashMap::new()).map_err(innermost))
+        }).unwrap().join().unwrap();
+        for error in result {
+            assert!(matches!(error, Err(EvalError::DepthExceeded { max_depth: 128, .. })), "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn eval_all_matches_sequential_evals() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("A\nINPUTS: a\nout = B(a)\n\nB\nINPUTS: a\nout = NOT(A(a))\n").unwrap();
+        let inputs = HashMap::from([(String::from("a"), 0b1100), (String::from("b"), 0b1010), (String::from("cin"), 0b0110), (String::from("sel"), 0b1001)]);
+        let chip_names = ["AND", "OR", "XOR", "FULLADDER", "MUX"];
+        let parallel = cpu.eval_all(&chip_names, &inputs).unwrap();
+        for chip_name in chip_names {
+            assert_eq!(parallel[chip_name], cpu.eval(cpu.chips[chip_name].clone(), &inputs).unwrap(), "{}", chip_name);
+        }
+        // A cyclic chip fails on a rayon worker the same way it does here
+        let error = cpu.eval_all(&["AND", "A", "XOR"], &inputs).unwrap_err();
+        assert!(matches!(innermost(error), EvalError::DepthExceeded { .. }));
+        assert!(matches!(cpu.eval(cpu.chips["A"].clone(), &inputs).map_err(innermost), Err(EvalError::DepthExceeded { .. })));
+    }
+
+    #[test]
+    fn check_calls_reports_too_few_arguments() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("T\nINPUTS: a\nout = XOR(a)\n").unwrap();
+        assert_eq!(cpu.check_calls("T").unwrap(), vec![String::from("T: XOR takes 2 inputs but 1 are given")]);
+        for chip_name in cpu.chips.keys().filter(|chip_name| *chip_name != "T") {
+            assert_eq!(cpu.check_calls(chip_name).unwrap(), Vec::<String>::new());
+        }
+    }
+
+    #[test]
+    fn chips_defined_twice_in_a_library_are_an_error() {
+        let chips = parse_library("A\nINPUTS: a\nout = NOT(a)\n\nB\nINPUTS: a\nout = A(a)\n").unwrap();
+        assert_eq!(chips.len(), 2);
+        let error = parse_library("A\nINPUTS: a\nout = NOT(a)\n\nA\nINPUTS: a\nout = a\n").unwrap_err();
+        assert!(matches!(&error, NandError::Parse(message) if message == "Chip A is defined more than once, again on line 5"), "{:?}", error);
+    }
+
+    #[test]
+    fn natives_without_out_default_to_their_smallest_output() {
+        let mut cpu = ChipEvaluator::new();
+        cpu.register_native("MAJ", Box::new(|inputs| {
+            let (a, b, c) = (inputs["a"], inputs["b"], inputs["c"]);
+            HashMap::from([(String::from("maj"), (a & b) | (a & c) | (b & c)), (String::from("min"), !((a & b) | (a & c) | (b & c))), (String::from("zero"), 0)])
+        }));
+        let inputs = HashMap::from([(String::from("x"), 0b1100), (String::from("y"), 0b1010), (String::from("z"), 0b0110)]);
+        for _ in 0..16 {
+            assert_eq!(cpu.eval(parse("out = MAJ(a: x, b: y, c: z)").unwrap(), &inputs).unwrap()["out"], 0b1110);
+        }
+        assert_eq!(cpu.eval(parse("out = MAJ.min(a: x, b: y, c: z)").unwrap(), &inputs).unwrap()["out"], !0b1110);
+    }
+
+    #[test]
+    fn libraries_load_in_source_order() {
+        let mut cpu = ChipEvaluator::new();
+        cpu.load_library("C\nINPUTS: a\nout = B(a)\n\nB\nINPUTS: a\nout = A(a)\n\nA\nINPUTS: a\nout = NAND(a, a)\n").unwrap();
+        assert_eq!(cpu.chip_names(), ["C", "B", "A"]);
+    }
+
+    #[test]
+    fn formats_clamp_widths_outside_a_byte() {
+        assert_eq!(as_signed(0xFF, 8), -1);
+        assert_eq!(as_signed(0b0111, 4), 7);
+        assert_eq!(as_signed(1, 0), -1);
+        assert_eq!(as_signed(0xFF, 16), -1);
+        assert_eq!(ValueFormat::Binary.format(1, 16), "0b00000001");
+        assert_eq!(ValueFormat::Hex.format(0xAB, 0), "0x1");
+        assert_eq!(ValueFormat::PerLane.format(0b10, 0), "0:0");
+        assert_eq!(ValueFormat::Signed.format(0x80, 9), "-128");
+    }
+
+    #[test]
+    fn repeated_chip_outputs_as_arguments_get_the_same_value() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("ADD\nINPUTS: a, b\nOUTPUTS: sum, carry\nsum = XOR(a, b)\ncarry = AND(a, b)\n\nT\nINPUTS: a, b\nout = NAND(ADD.sum(a, b), ADD.sum(a, b))\nmixed = NAND(ADD.sum(a, b), ADD.carry(a, b))\n").unwrap();
+        let inputs = HashMap::from([(String::from("a"), 0b1100), (String::from("b"), 0b1010)]);
+        let code = parse("out = NAND(ADD.sum(a, b), ADD.sum(a, b))\nmixed = NAND(ADD.sum(a, b), ADD.carry(a, b))").unwrap();
+        for output in [cpu.eval(code, &inputs).unwrap(), cpu.eval(parse("out = T.out(a, b)\nmixed = T.mixed(a, b)").unwrap(), &inputs).unwrap()] {
+            assert_eq!(output["out"], !0b0110);
+            assert_eq!(output["mixed"], 0b11111111);
+        }
+    }
+
+
+    #[test]
+    fn chips_without_arguments_can_be_called() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("ZERO\nout = 0\n\nONE\nout = NOT(ZERO())\n").unwrap();
+        assert_eq!(parse("out = ZERO()").unwrap()[1], Token::Expression(vec![Token::Chip(String::from("ZERO"))]));
+        assert_eq!(cpu.eval(parse("out = ZERO()").unwrap(), &HashMap::new()).unwrap()["out"], 0);
+        assert_eq!(cpu.eval(parse("out = ONE()").unwrap(), &HashMap::new()).unwrap()["out"], 0b11111111);
+    }
+
+    #[test]
+    fn flattened_netlists_are_cached_until_a_dependency_changes() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("T\nINPUTS: a, b\nout = XOR(NOT(a), b)\n\nU\nINPUTS: a, b\nout = XOR(a, b)\n").unwrap();
+        assert!(!cpu.is_flattened("T"));
+        let netlist = cpu.flatten("T").unwrap();
+        assert!(cpu.is_flattened("T"));
+        assert_eq!(cpu.flatten("T").unwrap().gates.len(), netlist.gates.len());
+        cpu.flatten("U").unwrap();
+
+        // NOT is expanded into T but not into U
+        cpu.load_chip("NOT", &parse("out = NAND(a, 1)").unwrap());
+        assert!(!cpu.is_flattened("T"));
+        assert!(cpu.is_flattened("U"));
+        cpu.flatten("T").unwrap();
+        cpu.load_chip("T", &parse("out = a").unwrap());
+        assert!(!cpu.is_flattened("T"));
+    }
+
+    #[test]
+    fn overflowing_literals_wrap_or_saturate() {
+        let mut cpu = ChipEvaluator::new();
+        cpu.set_width(4);
+        assert_eq!(cpu.literal_mode(), LiteralMode::Wrap);
+        assert_eq!(cpu.eval(parse("out = 0x1a").unwrap(), &HashMap::new()).unwrap()["out"], 0xa);
+        cpu.set_literal_mode(LiteralMode::Saturate);
+        assert_eq!(cpu.eval(parse("out = 0x1a").unwrap(), &HashMap::new()).unwrap()["out"], 0xf);
+        assert_eq!(cpu.eval(parse("out = 0x0a").unwrap(), &HashMap::new()).unwrap()["out"], 0xa);
+    }
+
+    #[test]
+    fn positional_inputs_past_z_are_named_aa_onwards() {
+        assert_eq!([0, 25, 26, 27, 51, 52, 701, 702].map(input_param_name), ["a", "z", "aa", "ab", "az", "ba", "zz", "aaa"]);
+
+        // Without declared inputs the 27th argument binds to aa
+        let mut cpu = ChipEvaluator::new();
+        cpu.load_library("WIDE\nout = NAND(z, aa)\n").unwrap();
+        let args: Vec<&str> = (0..27).map(|i| if i >= 25 { "x" } else { "0" }).collect();
+        let inputs = HashMap::from([(String::from("x"), 0b1100)]);
+        assert_eq!(cpu.eval(parse(&format!("out = WIDE({})", args.join(", "))).unwrap(), &inputs).unwrap()["out"], !0b1100);
+        assert_eq!(cpu.eval(parse(&format!("out = WIDE({}, 0)", args[..26].join(", "))).unwrap(), &inputs).unwrap()["out"], 0b11111111);
+    }
+
+    #[test]
+    fn aliases_evaluate_like_their_source() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.alias("ADD", "FULLADDER").unwrap();
+        assert_eq!(cpu.alias("SUB", "MISSING"), Err(UnknownChip(String::from("MISSING"))));
+        let inputs = HashMap::from([(String::from("x"), 0b11110000), (String::from("y"), 0b11001100), (String::from("z"), 0b10101010)]);
+        let aliased = cpu.eval(parse("sum = ADD.sum(x, y, z)\ncarry = ADD.carry(x, y, z)").unwrap(), &inputs).unwrap();
+        let original = cpu.eval(parse("sum = FULLADDER.sum(x, y, z)\ncarry = FULLADDER.carry(x, y, z)").unwrap(), &inputs).unwrap();
+        assert_eq!(aliased, original);
+        assert_eq!(cpu.outputs_of("ADD"), cpu.outputs_of("FULLADDER"));
+    }
+
+    #[test]
+    fn quoted_strings_are_single_tokens() {
+        assert_eq!(tokenize("out = LABEL(\"a,b(c)\", x)").unwrap(), ["out", "=", "LABEL", "(", "\"a,b(c)\"", ",", "x", ")"]);
+        assert!(matches!(tokenize("out = \"open"), Err(NandError::Tokenize(_))));
+    }
+
+    #[test]
+    fn inverted_arguments_evaluate_like_not() {
+        let cpu = ChipEvaluator::with_stdlib();
+        let inputs = HashMap::from([(String::from("a"), 0b1100), (String::from("b"), 0b1010)]);
+        let inverted = cpu.eval(parse("out = NAND(!a, b)\nnested = !NAND(!a, !XOR(a, b))").unwrap(), &inputs).unwrap();
+        let with_not = cpu.eval(parse("out = NAND(NOT(a), b)\nnested = NOT(NAND(NOT(a), NOT(XOR(a, b))))").unwrap(), &inputs).unwrap();
+        assert_eq!(inverted, with_not);
+        assert_eq!(inverted["out"], !(!0b1100 & 0b1010));
+
+        // Bindings only take a name or literal, so inverting one is an error rather than a binding to nothing
+        for source in ["out = NAND(a: !a, b: b)", "out = NAND(a: NOT(a), b: b)"] {
+            assert!(matches!(parse(source), Err(NandError::Lex { message, .. }) if message.starts_with("Binding a:")), "{}", source);
+        }
+    }
+
+    // Every gate and output of a netlist by name, as an export would write them
+    fn describe(netlist: &Netlist) -> Vec<String> {
+        let gates = netlist.gates.iter().map(|gate| format!("{} = NAND({}, {})", gate.name, netlist.signal_name(&gate.a), netlist.signal_name(&gate.b)));
+        gates.chain(netlist.outputs.iter().map(|(name, signal)| format!("{} = {}", name, netlist.signal_name(signal)))).collect()
+    }
+
+    #[test]
+    fn flattening_names_signals_the_same_every_time() {
+        let first = ChipEvaluator::with_stdlib();
+        let second = ChipEvaluator::with_stdlib();
+        let netlist = first.flatten("ADD8").unwrap();
+        assert_eq!(describe(&netlist), describe(&second.flatten("ADD8").unwrap()));
+        // Uncached too
+        let uncached = first.flatten_with("ADD8", FlattenOptions { inline_threshold: Some(usize::MAX) }).unwrap();
+        assert_eq!(describe(&netlist), describe(&uncached));
+        assert_eq!(describe(&first.flatten("XOR").unwrap())[0], "XOR_n0 = NAND(a, b)");
+    }
+
+    #[test]
+    fn lex_errors_name_the_statement() {
+        let error = parse("x = NAND(a, b)\ny = NOT(x)\n\nz = NAND((x), y)\nout = z").unwrap_err();
+        assert!(matches!(error, NandError::Lex { statement: 3, line: Some(4), .. }), "{:?}", error);
+        assert!(error.to_string().starts_with("Syntax error in statement 3 (line 4)."), "{}", error);
+        // Statements on one line are still counted apart
+        assert!(matches!(parse("x = NAND(a, b) y = NOT(x) z = (").unwrap_err(), NandError::Lex { statement: 3, .. }));
+    }
+
+    #[test]
+    fn default_annotations_pick_the_output_of_a_plain_call() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("HALF\nINPUTS: a, b\nOUTPUTS: sum, carry\n@default(carry)\nsum = XOR(a, b)\ncarry = AND(a, b)\n\nFIRST\nINPUTS: a, b\nOUTPUTS: sum, carry\nsum = XOR(a, b)\ncarry = AND(a, b)\n").unwrap();
+        let inputs = HashMap::from([(String::from("a"), 0b1100), (String::from("b"), 0b1010)]);
+        let output = cpu.eval(parse("out = HALF(a, b)\nsum = HALF.sum(a, b)\nfirst = FIRST(a, b)").unwrap(), &inputs).unwrap();
+        assert_eq!(output["out"], 0b1000);
+        assert_eq!(output["sum"], 0b0110);
+        assert_eq!(output["first"], 0b0110);
+        assert!(matches!(parse_library("@default(carry)\nHALF\nINPUTS: a, b\ncarry = AND(a, b)\n"), Err(NandError::Parse(_))));
+    }
+
+    #[test]
+    fn tokens_after_a_closing_paren_are_errors() {
+        let call = [Token::Chip(String::from("NAND")), Token::LParen, Token::Input(String::from("a")), Token::Comma, Token::Input(String::from("b")), Token::RParen];
+        assert!(parse_expressions(&call).is_ok());
+        let trailing = [&call[..], &[Token::Input(String::from("c"))]].concat();
+        assert_eq!(parse_expressions(&trailing), Err(NandError::Parse(String::from("Unexpected token after closing parenthesis: c"))));
+        assert_eq!(parse("out = NAND(NOT(a) b, c)"), Err(NandError::Parse(String::from("Unexpected token after closing parenthesis: b"))));
+        assert!(matches!(parse("out = NAND(a, b) c"), Err(NandError::Lex { .. })));
+    }
+
+    #[test]
+    fn equals_in_strings_and_comments_do_not_split_statements() {
+        assert_eq!(tokenize("label = \"a=b\"").unwrap(), ["label", "=", "\"a=b\""]);
+        assert_eq!(tokenize("out = NAND(a, b) // a = b\n// c = d").unwrap(), ["out", "=", "NAND", "(", "a", ",", "b", ")"]);
+        assert_eq!(parse("out = NAND(a, b) // a = b, c = NOT(d)").unwrap(), parse("out = NAND(a, b)").unwrap());
+    }
+
+    #[test]
+    fn chips_over_the_inline_threshold_become_calls() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("T\nINPUTS: a, b\nx = XOR(a, b)\nout = NOT(x)\n").unwrap();
+        let netlist = cpu.flatten_with("T", FlattenOptions { inline_threshold: Some(2) }).unwrap();
+        assert!(!cpu.is_flattened("T"));
+        // XOR has four gates and is called, NOT has one and is inlined
+        assert_eq!(netlist.calls.iter().map(|call| call.chip.as_str()).collect::<Vec<&str>>(), ["XOR"]);
+        assert_eq!(netlist.gates.len(), 1);
+        assert_eq!(netlist.subchips["XOR"].gates.len(), 4);
+        assert_eq!(cpu.flatten("T").unwrap().gates.len(), 5);
+
+        let inputs = HashMap::from([(String::from("a"), 0b1100), (String::from("b"), 0b1010)]);
+        assert_eq!(netlist.eval(&inputs), cpu.flatten("T").unwrap().eval(&inputs));
+    }
+
+    #[test]
+    fn each_format_shows_a_known_value() {
+        let value = 0b1010_0101;
+        assert_eq!(ValueFormat::Binary.format(value, 8), "0b10100101");
+        assert_eq!(ValueFormat::Hex.format(value, 8), "0xa5");
+        assert_eq!(ValueFormat::Decimal.format(value, 8), "165");
+        assert_eq!(ValueFormat::Signed.format(value, 8), "-91");
+        assert_eq!(ValueFormat::PerLane.format(value, 4), "0:1 1:0 2:1 3:0");
+        // Narrower widths only show the low bits
+        assert_eq!(ValueFormat::Binary.format(value, 4), "0b0101");
+        assert_eq!(ValueFormat::Signed.format(value, 3), "-3");
+    }
+
+    #[test]
+    fn unused_locals_are_warned_about() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("T\nINPUTS: a, b\nOUTPUTS: out\nn = NAND(a, b)\nm = NOT(n)\nout = NOT(nn)\n").unwrap();
+        assert_eq!(cpu.unused_locals("T"), Ok(vec![String::from("m")]));
+        assert!(cpu.check().contains(&String::from("T: m is assigned but never read")));
+        assert_eq!(cpu.unused_locals("MISSING"), Err(UnknownChip(String::from("MISSING"))));
+    }
+
+    #[test]
+    fn empty_programs_have_no_statements() {
+        assert_eq!(parse(""), Ok(vec![]));
+        assert_eq!(parse("  \n\t\n"), Ok(vec![]));
+        assert_eq!(parse("// nothing yet\n\n// still nothing\n"), Ok(vec![]));
+        assert_eq!(parse_library("// nothing yet\n"), Ok(vec![]));
+        assert_eq!(ChipEvaluator::new().eval(parse("").unwrap(), &HashMap::new()), Ok(HashMap::new()));
+    }
+
+    #[test]
+    fn selected_outputs_take_positional_inputs() {
+        let cpu = ChipEvaluator::with_stdlib();
+        let inputs = HashMap::from([(String::from("x"), 0b11110000), (String::from("y"), 0b11001100), (String::from("cin"), 0b10101010)]);
+        let output = cpu.eval(parse("carry = FULLADDER.carry(x, y, cin)\nsum = FULLADDER.sum(x, y, cin)").unwrap(), &inputs).unwrap();
+        assert_eq!(output["carry"], 0b11101000);
+        assert_eq!(output["sum"], 0b10010110);
+    }
+
+    #[test]
+    fn each_stage_has_its_own_error() {
+        let cpu = ChipEvaluator::with_stdlib();
+        assert!(matches!(parse("out = \"open"), Err(NandError::Tokenize(_))));
+        assert!(matches!(parse("out = NAND(a, b"), Err(NandError::Lex { statement: 1, line: Some(1), .. })));
+        assert!(matches!(parse("out = NAND(NOT(a) b, c)"), Err(NandError::Parse(_))));
+        let error = cpu.eval_expr("MISSING(a)", &HashMap::new()).unwrap_err();
+        assert!(matches!(&error, NandError::Eval(EvalError::UnknownChip(chip)) if chip == "MISSING"), "{:?}", error);
+        assert!(std::error::Error::source(&error).is_some());
+    }
+
+    #[test]
+    fn outputs_of_lists_outputs_in_order() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("SPLIT\nINPUTS: a, b\nlow = AND(a, b)\nhigh = OR(a, b)\n").unwrap();
+        assert_eq!(cpu.outputs_of("SPLIT"), vec!["low", "high"]);
+        assert_eq!(cpu.outputs_of("FULLADDER"), vec!["sum", "carry"]);
+        assert!(cpu.outputs_of("MISSING").is_empty());
+    }
+
+    #[test]
+    fn statements_continue_until_their_parentheses_close() {
+        assert_eq!(parse("out = NAND(\n  a,\n  b\n)\n"), parse("out = NAND(a, b)"));
+        let error = parse("x = NAND(\n  a,\n  b\n)\ny = NAND(a, b:)\n").unwrap_err();
+        assert!(matches!(error, NandError::Lex { statement: 2, line: Some(5), .. }), "{:?}", error);
+        let error = parse("x = NAND(\n  a,\n  b:\n)\n").unwrap_err();
+        assert!(matches!(error, NandError::Lex { statement: 1, line: Some(3), .. }), "{:?}", error);
+    }
+
+    #[test]
+    fn removed_chips_can_no_longer_be_called() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("INV\nINPUTS: a\nout = NAND(a, a)\n\nT\nINPUTS: a\nout = INV(a)\n").unwrap();
+        assert!(cpu.contains_chip("INV"));
+        cpu.flatten("T").unwrap();
+        assert_eq!(cpu.remove_chip("INV"), Some(parse("INPUTS: a\nout = NAND(a, a)").unwrap()));
+        assert!(!cpu.contains_chip("INV"));
+        assert!(!cpu.is_flattened("T"));
+        assert_eq!(cpu.remove_chip("INV"), None);
+        let error = cpu.eval(parse("out = T(a)").unwrap(), &HashMap::from([(String::from("a"), 1)])).unwrap_err();
+        assert_eq!(error.root_cause(), &EvalError::UnknownChip(String::from("INV")));
+    }
+
+    #[test]
+    fn eval_lanes_keeps_each_case_in_its_own_lane() {
+        let cpu = ChipEvaluator::with_stdlib();
+        let cases: Vec<HashMap<String, bool>> = (0..8).map(|i| HashMap::from([
+            (String::from("a"), i & 1 == 1),
+            (String::from("b"), i & 2 == 2),
+            (String::from("c"), i & 4 == 4),
+        ])).collect();
+        let results = cpu.eval_lanes("FULLADDER", &cases).unwrap();
+        assert_eq!(results.len(), 8);
+        for (i, result) in results.iter().enumerate() {
+            let bits = (i & 1) + ((i >> 1) & 1) + ((i >> 2) & 1);
+            assert_eq!(result["sum"], bits & 1 == 1, "case {}", i);
+            assert_eq!(result["carry"], bits >= 2, "case {}", i);
+        }
+    }
+
+    #[test]
+    fn the_primitive_can_be_renamed() {
+        let mut cpu = ChipEvaluator::new();
+        cpu.set_primitive("PNAND");
+        assert_eq!(cpu.primitive(), "PNAND");
+        cpu.load_library("INV\nINPUTS: a\nout = PNAND(a, a)\n").unwrap();
+        let inputs = HashMap::from([(String::from("a"), 0b1100), (String::from("b"), 0b1010)]);
+        assert_eq!(cpu.eval(parse("out = PNAND(a, INV(b))").unwrap(), &inputs).unwrap()["out"], !0b0100);
+        assert_eq!(cpu.flatten("INV").unwrap().gates.len(), 1);
+        // NAND is now an ordinary chip name
+        let error = cpu.eval(parse("out = NAND(a, b)").unwrap(), &inputs).unwrap_err();
+        assert_eq!(error.root_cause(), &EvalError::UnknownChip(String::from("NAND")));
+    }
+
+    #[test]
+    fn identical_expressions_share_gates() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("T\nINPUTS: a, b, c\nOUTPUTS: x, y, z\nx = NAND(XOR(a, b), c)\ny = NAND(XOR(a, b), c)\nz = NAND(c, XOR(a, b))\n").unwrap();
+        let netlist = cpu.flatten("T").unwrap();
+        let gate = |name: &str| match netlist.outputs.iter().find(|(output, _)| output == name) {
+            Some((_, Signal::Gate(i))) => *i,
+            other => panic!("{} is not a gate: {:?}", name, other),
+        };
+        assert_eq!(gate("x"), gate("y"));
+        // z shares the XOR but not the outer NAND, as its arguments are in another order
+        assert_ne!(gate("x"), gate("z"));
+        assert_eq!(netlist.gates.len(), 4 + 2);
+    }
+
+    #[test]
+    fn crlf_line_endings_tokenize_like_lf() {
+        let source = "// adds a and b\nx = NAND(a, b) // trailing\n/// doc\nout = NOT(x)\n";
+        let crlf = source.replace('\n', "\r\n");
+        assert_eq!(tokenize(&crlf).unwrap(), tokenize(source).unwrap());
+        assert_eq!(parse(&crlf), parse(source));
+        assert_eq!(parse_library(&format!("T\r\nINPUTS: a, b\r\n{}", crlf)), parse_library(&format!("T\nINPUTS: a, b\n{}", source)));
+    }
+
+    #[test]
+    fn inferred_inputs_follow_first_appearance() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("T\nx = AND(c, a)\ny = OR(x, b:b)\nout = MUX(y, a, sel: d)\n\nD\nINPUTS: z, y\nout = AND(y, z)\n").unwrap();
+        assert_eq!(cpu.inputs_of("T"), vec!["c", "a", "b", "d"]);
+        assert_eq!(cpu.inputs_of("T"), cpu.inputs_of("T"));
+        assert_eq!(cpu.inputs_of("D"), vec!["z", "y"]);
+        assert!(cpu.inputs_of("MISSING").is_empty());
+    }
+
+    #[test]
+    fn wide_values_join_by_listing_their_bits() {
+        // Two 4-bit values become one 8-bit value by listing the bits of both, there is no {lo, hi}
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("JOIN\nINPUTS: lo0, lo1, lo2, lo3, hi0, hi1, hi2, hi3\nOUTPUTS: b0, b1, b2, b3, b4, b5, b6, b7\n\
+            b0 = lo0\nb1 = lo1\nb2 = lo2\nb3 = lo3\nb4 = hi0\nb5 = hi1\nb6 = hi2\nb7 = hi3\n").unwrap();
+        let (lo, hi) = (0x5, 0xA);
+        let mut inputs = HashMap::<String, bool>::new();
+        for i in 0..4 {
+            inputs.insert(format!("lo{}", i), (lo >> i) & 1 == 1);
+            inputs.insert(format!("hi{}", i), (hi >> i) & 1 == 1);
+        }
+        let outputs = cpu.eval_bools("JOIN", &inputs).unwrap();
+        let joined = (0..8).fold(0u8, |value, i| value | ((outputs[&format!("b{}", i)] as u8) << i));
+        assert_eq!(joined, 0xA5);
+        assert!(parse("out = {a, b}").is_err());
+    }
+
+    #[test]
+    fn eval_errors_quote_the_statement() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("ADDER\nINPUTS: a, b, c\nsum = FULLADDER.sum(a, b, c)\ncarry = FADD.carry(a, b: b, c)\n").unwrap();
+        let inputs = HashMap::from([(String::from("x"), 1)]);
+        let error = cpu.eval(parse("out = ADDER.carry(x, x, x)").unwrap(), &inputs).unwrap_err();
+        assert_eq!(error.root_cause(), &EvalError::UnknownChip(String::from("FADD")));
+        // The innermost statement is quoted, not the call that reached it
+        assert_eq!(error.to_string(), "Unknown chip: FADD in chip ADDER at 'carry = FADD.carry(a, b: b, c)'");
+    }
+
+    #[test]
+    fn checked_loads_refuse_to_replace_a_chip() {
+        let mut cpu = ChipEvaluator::new();
+        let first = parse("INPUTS: a\nout = NAND(a, a)").unwrap();
+        let second = parse("INPUTS: a\nout = a").unwrap();
+        assert_eq!(cpu.load_chip_checked("INV", &first), Ok(()));
+        assert_eq!(cpu.load_chip_checked("INV", &second), Err(DuplicateChip(String::from("INV"))));
+        assert_eq!(cpu.remove_chip("INV"), Some(first));
+        // Plain load_chip still replaces
+        cpu.load_chip("INV", &second);
+        cpu.load_chip("INV", &second);
+        assert!(cpu.contains_chip("INV"));
+    }
+
+    #[test]
+    fn probe_sinks_see_intermediate_values() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        let seen = Arc::new(Mutex::new(Vec::<(String, u8)>::new()));
+        let sink_seen = seen.clone();
+        cpu.add_probe_sink("n", Box::new(move |name, value| sink_seen.lock().unwrap().push((name.to_string(), value))));
+        let inputs = HashMap::from([(String::from("a"), 0b1100), (String::from("b"), 0b1010)]);
+        // XOR assigns n = NAND(a, b) before its output
+        assert_eq!(cpu.eval(parse("out = XOR(a, b)").unwrap(), &inputs).unwrap()["out"], 0b0110);
+        assert_eq!(*seen.lock().unwrap(), vec![(String::from("n"), !0b1000)]);
+
+        cpu.clear_probe_sinks();
+        cpu.eval(parse("out = XOR(a, b)").unwrap(), &inputs).unwrap();
+        assert_eq!(seen.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn outputs_named_like_an_input_are_diagnosed() {
+        let mut cpu = ChipEvaluator::new();
+        cpu.load_library("T\nINPUTS: x, y\nOUTPUTS: x, z\nz = NAND(x, y)\nx = NAND(x, x)\n").unwrap();
+        assert_eq!(cpu.shadowed_inputs("T"), Ok(vec![String::from("x")]));
+        assert_eq!(cpu.check(), vec!["T: input x is also assigned, statements after the assignment read the assigned value"]);
+        // Statements before the assignment read the input
+        let inputs = HashMap::from([(String::from("x"), 0b1100), (String::from("y"), 0b1010)]);
+        let output = cpu.eval(parse("z = T.z(x, y)\nx = T.x(x, y)").unwrap(), &inputs).unwrap();
+        assert_eq!(output["z"], !0b1000);
+        assert_eq!(output["x"], !0b1100);
+    }
+
+    #[test]
+    fn outputs_without_an_assignment_are_errors() {
+        for source in ["OUT", "OUT =", "OUT = ", "x = NAND(a, b)\nOUT"] {
+            let error = parse(source).unwrap_err();
+            assert!(matches!(&error, NandError::Lex { message, .. } if message.contains("'OUT' has no assignment")), "{}: {:?}", source, error);
+        }
+    }
+
+    #[test]
+    fn nand_with_three_inputs_is_an_arity_error() {
+        let cpu = ChipEvaluator::new();
+        let inputs = HashMap::from([(String::from("a"), 1), (String::from("b"), 1), (String::from("c"), 1)]);
+        let arity = EvalError::WrongArity { chip: String::from("NAND"), expected: 2, given: 3 };
+        assert_eq!(cpu.eval(parse("out = NAND(a, b, c)").unwrap(), &inputs).unwrap_err().root_cause(), &arity);
+        assert_eq!(arity.to_string(), "NAND takes 2 inputs but 3 are given");
+        // Positional inputs need not be named a and b
+        assert_eq!(cpu.eval(parse("out = NAND(c, b)").unwrap(), &inputs).unwrap()["out"], 0b11111110);
+    }
+
+    #[test]
+    fn eval_bools_of_and_matches_logical_and() {
+        let cpu = ChipEvaluator::with_stdlib();
+        for (a, b) in [(false, false), (false, true), (true, false), (true, true)] {
+            let inputs = HashMap::from([(String::from("a"), a), (String::from("b"), b)]);
+            assert_eq!(cpu.eval_bools("AND", &inputs).unwrap(), HashMap::from([(String::from("out"), a && b)]));
+        }
+        assert_eq!(cpu.eval_bools("MISSING", &HashMap::new()), Err(EvalError::UnknownChip(String::from("MISSING"))));
+    }
+
+    #[test]
+    fn bare_arguments_named_like_the_inputs_bind_like_pairs() {
+        let cpu = ChipEvaluator::with_stdlib();
+        let inputs = HashMap::from([(String::from("a"), 0b1100), (String::from("b"), 0b1010), (String::from("sel"), 0b0110)]);
+        let shorthand = cpu.eval(parse("out = NAND(a, b)\nmux = MUX(a, b, sel)").unwrap(), &inputs).unwrap();
+        let pairs = cpu.eval(parse("out = NAND(a: a, b: b)\nmux = MUX(a: a, b: b, sel: sel)").unwrap(), &inputs).unwrap();
+        assert_eq!(shorthand, pairs);
+        // Bare arguments are still positional when the names differ
+        let swapped = cpu.eval(parse("mux = MUX(b, a, sel)").unwrap(), &inputs).unwrap();
+        assert_eq!(swapped["mux"], cpu.eval(parse("mux = MUX(a: b, b: a, sel: sel)").unwrap(), &inputs).unwrap()["mux"]);
+        assert_ne!(swapped["mux"], pairs["mux"]);
+    }
+
+    #[test]
+    fn lazy_tokens_match_eager_ones() {
+        let source = "/// doc\nT\nINPUTS: a, b\nx, y = NAND(a: a, b)// c\nout = LABEL(\"a b\", !x)\n";
+        let lazy: Vec<(String, usize)> = tokenize_iter(source.chars()).collect::<Result<_, _>>().unwrap();
+        assert_eq!(lazy, tokenize_lines(source).unwrap());
+        let mut tokens = tokenize_iter("out = \"open".chars());
+        assert_eq!(tokens.next(), Some(Ok((String::from("out"), 1))));
+        assert_eq!(tokens.next(), Some(Ok((String::from("="), 1))));
+        assert!(matches!(tokens.next(), Some(Err(NandError::Tokenize(_)))));
+        assert_eq!(tokens.next(), None);
+    }
+
+    #[test]
+    fn fan_out_assigns_every_name_the_same_value() {
+        let cpu = ChipEvaluator::new();
+        let inputs = HashMap::from([(String::from("a"), 0b1100), (String::from("b"), 0b1010)]);
+        let code = parse("x, y = NAND(a:a,b:b)").unwrap();
+        assert_eq!(statement_outputs(&code), vec!["x", "y"]);
+        let output = cpu.eval(code, &inputs).unwrap();
+        assert_eq!(output, HashMap::from([(String::from("x"), !0b1000), (String::from("y"), !0b1000)]));
+    }
+
+    #[test]
+    fn trailing_commas_are_ignored_and_leading_ones_rejected() {
+        assert_eq!(parse("out = NAND(a, b,)"), parse("out = NAND(a, b)"));
+        assert_eq!(parse("out = NAND(\n  a,\n  NOT(b,),\n)"), parse("out = NAND(a, NOT(b))"));
+        for source in ["out = NAND(,a,b)", "out = NAND(a,,b)"] {
+            assert_eq!(parse(source), Err(NandError::Parse(String::from("Missing argument before ',' in call to NAND"))), "{}", source);
+        }
+    }
+
+    #[test]
+    fn doc_comments_attach_to_the_chip_below() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("/// Inverts a\n/// twice\nBUF\nINPUTS: a\nout = NOT(NOT(a))\n\n// Not documentation\nPLAIN\nINPUTS: a\nout = a\n").unwrap();
+        assert_eq!(cpu.doc_of("BUF"), Some(String::from("Inverts a\ntwice")));
+        assert_eq!(cpu.doc_of("PLAIN"), None);
+        assert_eq!(cpu.doc_of("MUX"), Some(String::from("a when sel is 0, b when sel is 1")));
+        assert_eq!(cpu.doc_of("MISSING"), None);
+    }
+
+    #[test]
+    fn dotted_names_select_at_most_one_output() {
+        for source in ["out = FOO.out.bit(a)", "out = FOO..out(a)", "out = .out(a)", "out = FOO.(a)"] {
+            let error = parse(source).unwrap_err();
+            assert!(matches!(&error, NandError::Lex { message, .. } if message.starts_with("Invalid chip name") && message.ends_with("expected CHIP or CHIP.output")), "{}: {:?}", source, error);
+        }
+        assert_eq!(parse("out = FOO.out(a)").unwrap()[1], Token::Expression(vec![Token::ChipIO(String::from("FOO"), String::from("out")), Token::Input(String::from("a"))]));
+    }
+
+    #[test]
+    fn profiles_have_an_entry_per_chip_run() {
+        let cpu = ChipEvaluator::with_stdlib();
+        let inputs = HashMap::from([(String::from("a"), 0b1100), (String::from("b"), 0b1010), (String::from("c"), 0b0110)]);
+        let (output, profile) = cpu.eval_profiled("FULLADDER", &inputs).unwrap();
+        assert_eq!(output["carry"], 0b1110);
+        let mut chip_names: Vec<&String> = profile.keys().collect();
+        chip_names.sort();
+        assert_eq!(chip_names, ["AND", "FULLADDER", "NOT", "OR", "XOR"]);
+        // Callers include the time of the chips they call
+        assert!(profile["FULLADDER"] >= profile["OR"]);
+        assert_eq!(cpu.eval_profiled("MISSING", &inputs).unwrap_err(), EvalError::UnknownChip(String::from("MISSING")));
+    }
+
+    #[test]
+    fn evals_carry_no_state_between_runs() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        // q reads the input q, unbound here and so 0, rather than the last value assigned
+        cpu.load_library("LATCH\nINPUTS: d\nq = OR(q, d)\n").unwrap();
+        let set = HashMap::from([(String::from("d"), 0xff)]);
+        let clear = HashMap::from([(String::from("d"), 0)]);
+        assert_eq!(cpu.eval_all(&["LATCH"], &set).unwrap()["LATCH"]["q"], 0xff);
+        assert_eq!(cpu.eval_all(&["LATCH"], &clear).unwrap()["LATCH"]["q"], 0);
+        // Presetting the state is binding the input
+        let preset = HashMap::from([(String::from("d"), 0), (String::from("q"), 0b1010)]);
+        assert_eq!(cpu.eval_all(&["LATCH"], &preset).unwrap()["LATCH"]["q"], 0b1010);
+        assert_eq!(cpu.eval(parse("q = DFF(d)").unwrap(), &set).unwrap_err().root_cause(), &EvalError::UnknownChip(String::from("DFF")));
+    }
+
+    #[test]
+    fn tokens_display_as_source() {
+        let code = parse("out = FULLADDER.carry( a:x,NAND(y, !z),  0x0f )").unwrap();
+        assert_eq!(code[0].to_string(), "out");
+        assert_eq!(code[1].to_string(), "FULLADDER.carry(a: x, NAND(y, !z), 15)");
+        assert_eq!(Token::IO(String::from("sel"), String::from("1")).to_string(), "sel: 1");
+        assert_eq!(Token::Inputs(vec![String::from("a"), String::from("b")]).to_string(), "INPUTS: a, b");
+    }
+
+    #[test]
+    fn exports_of_the_same_library_are_identical() {
+        let export = || {
+            let cpu = ChipEvaluator::with_stdlib();
+            let netlist = cpu.flatten_with("ADD8", FlattenOptions { inline_threshold: Some(0) }).unwrap();
+            let mut library = Vec::<u8>::new();
+            cpu.write_bincode(&mut library).unwrap();
+            (format!("{:?}", netlist), library, cpu.chip_names().to_vec())
+        };
+        assert_eq!(export(), export());
+    }
+
+    #[test]
+    fn empty_chips_evaluate_to_zero() {
+        let mut cpu = ChipEvaluator::new();
+        cpu.load_library("EMPTY\nINPUTS: a\n").unwrap();
+        let inputs = HashMap::from([(String::from("a"), 0xff)]);
+        let result = cpu.eval_all(&["EMPTY"], &inputs).unwrap();
+        assert!(result["EMPTY"].is_empty());
+        assert_eq!(get_first_output(&result["EMPTY"]), 0);
+        assert_eq!(cpu.eval(parse("out = NAND(EMPTY(a), 1)").unwrap(), &inputs).unwrap()["out"], 0xff);
+    }
+
+    #[test]
+    fn annotations_above_a_chip_are_its_attributes() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("@area(12) @vendor(\"acme corp\")\n@flat()\n/// Inverts a\nINV\nINPUTS: a\nout = NOT(a)\n\nPLAIN\nINPUTS: a\nout = a\n").unwrap();
+        assert_eq!(cpu.attributes_of("INV"), HashMap::from([
+            (String::from("area"), String::from("12")),
+            (String::from("vendor"), String::from("acme corp")),
+            (String::from("flat"), String::new()),
+        ]));
+        assert_eq!(cpu.doc_of("INV"), Some(String::from("Inverts a")));
+        assert!(cpu.attributes_of("PLAIN").is_empty());
+        assert!(cpu.attributes_of("MISSING").is_empty());
+    }
+
+    #[test]
+    fn literals_take_any_base_with_underscores() {
+        for (source, value) in [("0o17", 15), ("0o3_77", 255), ("0b1010_1010", 0b1010_1010), ("0b_1111", 15), ("0xA_5", 0xA5), ("2_55", 255), ("1_000", 1000)] {
+            assert_eq!(parse_literal(source), Some(value), "{}", source);
+        }
+        for source in ["0o8", "0o_", "_12", "0b102"] {
+            assert_eq!(parse_literal(source), None, "{}", source);
+        }
+        assert_eq!(lex_literal("0o1"), Some(Token::Const(1)));
+        let cpu = ChipEvaluator::new();
+        assert_eq!(cpu.eval(parse("out = NAND(0o17, 0b0000_0101)").unwrap(), &HashMap::new()).unwrap()["out"], !0b0101);
+    }
+
+    #[test]
+    fn overrides_only_change_the_clone() {
+        let cpu = ChipEvaluator::with_stdlib();
+        let stub = cpu.clone_with_overrides(HashMap::from([(String::from("AND"), parse("INPUTS: a, b\nout = 0").unwrap())]));
+        let inputs = HashMap::from([(String::from("a"), 0b1100), (String::from("b"), 0b1010)]);
+        let program = parse("and = AND(a, b)\nor = OR(a, b)").unwrap();
+        assert_eq!(stub.eval(program.clone(), &inputs).unwrap(), HashMap::from([(String::from("and"), 0), (String::from("or"), 0b1110)]));
+        assert_eq!(cpu.eval(program, &inputs).unwrap()["and"], 0b1000);
+        assert_eq!(cpu.clone_with_overrides(HashMap::new()).chip_names(), cpu.chip_names());
+    }
+
+    #[test]
+    fn reduce_and_folds_from_the_left() {
+        let reduced = parse("out = REDUCE(AND, a, b, c)").unwrap();
+        let nested = parse("out = AND(AND(a, b), c)").unwrap();
+        let Token::Expression(e_codes) = &reduced[1] else { panic!("{:?}", reduced) };
+        assert_eq!(expand_reduce(e_codes).as_ref(), Some(&nested[1]));
+
+        let cpu = ChipEvaluator::with_stdlib();
+        for bits in 0..8u8 {
+            let value = |bit: u8| if bits & bit != 0 { 0xff } else { 0 };
+            let inputs = HashMap::from([(String::from("a"), value(1)), (String::from("b"), value(2)), (String::from("c"), value(4))]);
+            assert_eq!(cpu.eval(reduced.clone(), &inputs), cpu.eval(nested.clone(), &inputs), "{:03b}", bits);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+use crate::NandError;
+
+struct Macro {
+    params: Option<Vec<String>>, // None for `#define NAME body`, which is replaced without arguments
+    body: String,
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// `#define NAME(a, b) body` or `#define NAME body`
+fn parse_define(line: &str) -> Result<(String, Macro), NandError> {
+    let rest = line.split("//").next().unwrap().trim_start().strip_prefix("#define").unwrap().trim_start();
+    let name_end = rest.find(|c: char| !is_name_char(c)).unwrap_or(rest.len());
+    let name = &rest[..name_end];
+    if name.is_empty() {
+        return Err(NandError::Parse(format!("Expected a macro name: {}", line.trim())));
+    }
+
+    let rest = &rest[name_end..];
+    if let Some(rest) = rest.strip_prefix('(') {
+        let close = rest.find(')').ok_or_else(|| NandError::Parse(format!("Missing ')' after the parameters of macro {}", name)))?;
+        let params: Vec<String> = rest[..close].split(',').map(|param| param.trim().to_string()).filter(|param| !param.is_empty()).collect();
+        return Ok((name.to_string(), Macro { params: Some(params), body: rest[close + 1..].trim().to_string() }));
+    }
+    Ok((name.to_string(), Macro { params: None, body: rest.trim().to_string() }))
+}
+
+// The arguments of an invocation, `text` starting right after its '('. Also returns how much of
+// `text` they took up, closing ')' included.
+fn split_arguments(name: &str, text: &str) -> Result<(Vec<String>, usize), NandError> {
+    let mut arguments = Vec::<String>::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => {
+                if !current.trim().is_empty() || !arguments.is_empty() {
+                    arguments.push(current.trim().to_string());
+                }
+                return Ok((arguments, i + 1));
+            },
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                arguments.push(current.trim().to_string());
+                current.clear();
+                continue;
+            },
+            _ => {},
+        }
+        current.push(c);
+    }
+    Err(NandError::Parse(format!("Missing ')' after the arguments of macro {}", name)))
+}
+
+// Replaces whole-word occurrences of each parameter
+fn substitute(body: &str, params: &[String], arguments: &[String]) -> String {
+    let mut result = String::new();
+    let mut word = String::new();
+    for c in body.chars().chain(std::iter::once('\n')) {
+        if is_name_char(c) {
+            word.push(c);
+            continue;
+        }
+        match params.iter().position(|param| *param == word) {
+            Some(index) => result += &arguments[index],
+            None => result += &word,
+        }
+        word.clear();
+        result.push(c);
+    }
+    result.pop();
+    result
+}
+
+// Expands every invocation in `text`. `active` holds the macros being expanded, one of them
+// showing up again means it would never stop.
+fn expand(text: &str, macros: &HashMap<String, Macro>, active: &mut Vec<String>) -> Result<String, NandError> {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(is_name_char) {
+        // Output selectors such as the `sum` of `ADDER.sum` are never macros
+        let after_dot = rest[..start].ends_with('.');
+        result += &rest[..start];
+        rest = &rest[start..];
+        let end = rest.find(|c: char| !is_name_char(c)).unwrap_or(rest.len());
+        let name = &rest[..end];
+
+        let Some(definition) = macros.get(name).filter(|_| !after_dot) else {
+            result += name;
+            rest = &rest[end..];
+            continue;
+        };
+        if active.iter().any(|active_name| active_name == name) {
+            return Err(NandError::Parse(format!("Macro {} expands to itself: {} -> {}", name, active.join(" -> "), name)));
+        }
+
+        let (body, used) = match &definition.params {
+            None => (definition.body.clone(), end),
+            // Without parentheses the name is left alone, it may be a chip or signal of the same name
+            Some(_) if !rest[end..].starts_with('(') => {
+                result += name;
+                rest = &rest[end..];
+                continue;
+            },
+            Some(params) => {
+                let (arguments, length) = split_arguments(name, &rest[end + 1..])?;
+                if arguments.len() != params.len() {
+                    return Err(NandError::Parse(format!("Macro {} takes {} arguments but {} are given", name, params.len(), arguments.len())));
+                }
+                let arguments = arguments.iter().map(|argument| expand(argument, macros, active)).collect::<Result<Vec<String>, NandError>>()?;
+                (substitute(&definition.body, params, &arguments), end + 1 + length)
+            },
+        };
+        active.push(name.to_string());
+        result += &expand(&body, macros, active)?;
+        active.pop();
+        rest = &rest[used..];
+    }
+    result += rest;
+    Ok(result)
+}
+
+// Applies the `#define` lines of a source to the rest of it. The definitions are left as blank
+// lines so line numbers still match the source, and only apply to lines after them.
+pub fn expand_macros(source: &str) -> Result<String, NandError> {
+    if !source.contains("#define") {
+        return Ok(source.to_string());
+    }
+    let mut macros = HashMap::<String, Macro>::new();
+    let mut result = String::new();
+    let mut statement = String::new();
+    let mut paren_count = 0;
+    for line in source.lines() {
+        if paren_count == 0 && line.trim_start().starts_with("#define") {
+            let (name, definition) = parse_define(line)?;
+            macros.insert(name, definition);
+            result.push('\n');
+            continue;
+        }
+        // Comment lines, doc comments included, are kept. Comments after code are dropped.
+        if paren_count == 0 && line.trim_start().starts_with("//") {
+            result += line;
+            result.push('\n');
+            continue;
+        }
+        // An invocation may be split over several lines like any call, so whole statements are expanded
+        let code = line.split("//").next().unwrap();
+        for c in code.chars() {
+            if c == '(' {
+                paren_count += 1;
+            }
+            else if c == ')' {
+                paren_count -= 1;
+            }
+        }
+        statement += code;
+        statement.push('\n');
+        if paren_count <= 0 {
+            paren_count = 0;
+            result += &expand_lines(&statement, &macros)?;
+            statement.clear();
+        }
+    }
+    result += &expand_lines(&statement, &macros)?;
+    Ok(result)
+}
+
+// Expands a statement, padding it with blank lines when an invocation spread over several lines
+// got shorter, so the next statement still starts on its own line number
+fn expand_lines(statement: &str, macros: &HashMap<String, Macro>) -> Result<String, NandError> {
+    let mut expanded = expand(statement, macros, &mut Vec::new())?;
+    let missing = statement.matches('\n').count().saturating_sub(expanded.matches('\n').count());
+    expanded += &"\n".repeat(missing);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn macros_expand_before_parsing() {
+        let source = "#define INV(x) NAND(x, x)\n#define ONE 1\nout = INV(AND(a, ONE))\n";
+        assert_eq!(expand_macros(source).unwrap(), "\n\nout = NAND(AND(a, 1), AND(a, 1))\n");
+        assert_eq!(parse(source), parse("out = NAND(AND(a, 1), AND(a, 1))"));
+        // Lines before a definition are left alone
+        assert_eq!(expand_macros("x = INV(a)\n#define INV(x) NAND(x, x)\n").unwrap(), "x = INV(a)\n\n");
+    }
+
+    #[test]
+    fn recursive_macros_are_errors() {
+        let error = expand_macros("#define A(x) B(x)\n#define B(x) A(x)\nout = A(a)\n").unwrap_err();
+        assert_eq!(error, NandError::Parse(String::from("Macro A expands to itself: A -> B -> A")));
+        assert!(expand_macros("#define INV(x) NAND(x, x)\nout = INV(a, b)\n").is_err());
+    }
+}
@@ -1,440 +1,282 @@
-use std::{time::Instant, collections::HashMap, hash::Hash, ops::{BitAnd, Not}};
+use std::{collections::HashMap, env, fs, io, path::{Path, PathBuf}, process, time::Instant};
 
-#[derive(Debug, Clone)]
-enum Token {
-    Chip(String),
-    ChipIO(String, String), // CHIP_NAME[.CHIP_OUTPUT] - Defaults to the first output
-    Input(String),
-    IO(String, String),
-    Output(String),
-    True,
-    False,
-    Assign,
-    LParen,
-    RParen,
-    Comma,
-    Expression(Vec<Token>),
+use nandscript::{ChipEvaluator, Executable, Token, ValueFormat, get_first_output, json_string, lex_literal, nand_files, parse, read_library, statement_outputs};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text(ValueFormat),
+    Json,
+}
+
+// How much diagnostic output goes to stderr, results are always printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
 }
 
+struct Options {
+    format: Format,
+    verbosity: Verbosity,
+    width: u32,
+    libraries: Vec<String>,
+    expression: Option<String>,
+    check: Option<String>,
+    dump_ast: bool,
+    program: Option<String>,
+    inputs: Vec<(String, String)>,
+}
 
-fn tokenize(code: &str) -> Vec<String> {
-    let mut result: Vec<String> = Vec::new();
+fn usage() -> String {
+    String::from("Usage: nandscript [--quiet|--verbose] [--dump-ast] [--format binary|hex|decimal|signed|lanes|json] [--width N] [--lib FILE]... [--eval EXPRESSION | PROGRAM] [INPUT=VALUE]...\n       nandscript --check FILE|DIRECTORY")
+}
 
-    let mut currentWord: String = String::new();
-    let mut isComment = false;
-    for c in code.chars() {
-        if isComment {
-            if c == '\n' {
-                isComment = false;
-            }
-            continue;
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {format: Format::Text(ValueFormat::Binary), verbosity: Verbosity::Normal, width: 8, libraries: vec![], expression: None, check: None, dump_ast: false, program: None, inputs: vec![]};
+    let mut arg_iter = args.iter();
+    while let Some(arg) = arg_iter.next() {
+        if arg == "--format" {
+            options.format = match arg_iter.next().map(|x| x.as_str()) {
+                Some("binary") => Format::Text(ValueFormat::Binary),
+                Some("hex") => Format::Text(ValueFormat::Hex),
+                Some("decimal") => Format::Text(ValueFormat::Decimal),
+                Some("signed") => Format::Text(ValueFormat::Signed),
+                Some("lanes") => Format::Text(ValueFormat::PerLane),
+                Some("json") => Format::Json,
+                other => return Err(format!("Unknown format: {}", other.unwrap_or(""))),
+            };
         }
-        if c.is_whitespace() {
-            continue;
+        else if arg == "--quiet" {
+            options.verbosity = Verbosity::Quiet;
         }
-        if c == '(' || c == ')' || c == '=' || c == ',' {
-            if currentWord.len() > 0 {
-                result.push(currentWord.clone());
-            }
-            currentWord.clear();
-            result.push(c.into());
-            continue;
+        else if arg == "--verbose" {
+            options.verbosity = Verbosity::Verbose;
         }
-        currentWord += &c.to_string();
-        if currentWord == "//" {
-            isComment = true;
-            currentWord.clear();
+        else if arg == "--width" {
+            options.width = match arg_iter.next().map(|x| x.parse::<u32>()) {
+                Some(Ok(width)) if (1..=8).contains(&width) => width,
+                _ => return Err(String::from("--width expects a number from 1 to 8")),
+            };
         }
-    }
-    if currentWord.len() > 0 {
-        result.push(currentWord);
-    }
-
-    result
-}
-
-fn lex(tokens: &Vec<String>) -> Vec<Token>{
-    let mut result: Vec<Token> = Vec::new();
-    let mut hasOutput = false;
-    let mut assigning = false;
-    let mut parenCount = 0;
-
-    for tok in tokens {
-        if !hasOutput {
-            if tok == "(" || tok == ")" || tok == "," {
-                panic!("Syntax error. Unexpected token: {}, expected output name", tok);
+        else if arg == "--lib" {
+            match arg_iter.next() {
+                Some(path) => options.libraries.push(path.clone()),
+                None => return Err(String::from("--lib expects a file")),
             }
-            result.push(Token::Output(tok.into()));
-            hasOutput = true;
         }
-        else {
-            if !assigning {
-                if tok != "=" {
-                    panic!("Unexpected token {}, expected '='", tok);
-                }
-                result.push(Token::Assign);
-                assigning = true
-            }
-            else {
-                // RHS
-                if tok == ")" {
-                    result.push(Token::RParen);
-                    parenCount -= 1;
-                    if parenCount == 0 {
-                        // End of current statement
-                        assigning = false;
-                        hasOutput = false;
-                    }
-                }
-                else if tok == "(" {
-                    // The previous token which was misidentified as an input is now a chip
-                    if result.len() == 0 || match result.last().unwrap() {
-                        Token::Chip(_) => true,
-                        Token::Input(_) => false,
-                        Token::Output(_) => true,
-                        Token::True => true,
-                        Token::False => true,
-                        Token::Assign => true,
-                        Token::LParen => true,
-                        Token::RParen => true,
-                        Token::Comma => true,
-                        Token::Expression(_) => true,
-                        Token::IO(_, _) => true,
-                        Token::ChipIO(_, _) => true,
-                    } {
-                        panic!("Unexpected token: {}", tok);
-                    }
-                    
-                    // Can actually convert previous from input to chip now
-                    let last_token = result.last().unwrap().clone();
-                    if let Token::Input(x) = last_token {
-                        // This will always be the case
-                        result.pop();
-                        result.push(Token::Chip(x.into()));
-                        result.push(Token::LParen);
-                    }
-                    parenCount += 1;
-                }
-                else if tok == "," {
-                    result.push(Token::Comma);
-                }
-                else {
-                    if tok.to_lowercase() == "true" || tok == "1" {
-                        result.push(Token::True);
-                    }
-                    else if tok.to_ascii_lowercase() == "false" || tok == "0" {
-                        result.push(Token::False);
-                    }
-                    else {
-                        result.push(Token::Input(tok.into()));
-                    }
-                }
+        else if arg == "--eval" {
+            match arg_iter.next() {
+                Some(expression) => options.expression = Some(expression.clone()),
+                None => return Err(String::from("--eval expects an expression")),
             }
         }
-    }
-
-    result
-}
-
-fn lex2(tokens: &[Token]) -> Vec<Token> {
-    let mut result = Vec::<Token>::new();
-    let mut current_tokens = vec![];
-
-    for tok in tokens {
-        if let Token::Output(_) = tok {
-            if current_tokens.len() > 0 {
-                // Flush current tokens as expression
-                result.push(parse_expressions(&current_tokens));
-                current_tokens.clear();
+        else if arg == "--dump-ast" {
+            options.dump_ast = true;
+        }
+        else if arg == "--check" {
+            match arg_iter.next() {
+                Some(path) => options.check = Some(path.clone()),
+                None => return Err(String::from("--check expects a file or directory")),
             }
-            result.push(tok.clone());
         }
-        else if let Token::Assign = tok {
-            // result.push(tok.clone());
-            // Clear the current tokens
-            current_tokens.clear();
+        else if let Some((name, value)) = arg.split_once('=') {
+            options.inputs.push((name.to_string(), value.to_string()));
+        }
+        else if arg.starts_with("--") || options.program.is_some() {
+            return Err(format!("Unexpected argument: {}", arg));
         }
         else {
-            // Otherwise add to current tokens
-            if let Token::Chip(x) = tok {
-                if x.contains('.') {
-                    current_tokens.push(Token::ChipIO(x.split('.').nth(0).unwrap().into(), x.split('.').nth(1).unwrap().into()));
-                }
-                else {
-                    current_tokens.push(tok.clone());
-                }
-            }
-            else {
-                current_tokens.push(tok.clone());
-            }
+            options.program = Some(arg.clone());
         }
     }
-    if current_tokens.len() > 0 {
-        // Flush current tokens as expression
-        result.push(parse_expressions(&current_tokens));
-    }
+    Ok(options)
+}
 
-    result
+fn log(options: &Options, level: Verbosity, message: &str) {
+    if options.verbosity >= level {
+        eprintln!("{}", message);
+    }
 }
 
-fn lex_final(tokens: &[Token]) -> Vec<Token> {
-    let mut result = Vec::<Token>::new();
-    for tok in tokens {
-        match tok {
-            Token::Chip(x) => {
-                if x.contains('.') {
-                    result.push(Token::ChipIO(x.split('.').nth(0).unwrap().into(), x.split('.').nth(1).unwrap().into()));
-                }
-                else {
-                    result.push(tok.clone())
-                }
-            },
-            Token::ChipIO(_, _) => result.push(tok.clone()),
-            Token::Input(_) => result.push(tok.clone()),
-            Token::IO(_, _) => result.push(tok.clone()),
-            Token::Output(_) => result.push(tok.clone()),
-            Token::True => result.push(tok.clone()),
-            Token::False => result.push(tok.clone()),
-            Token::Assign => result.push(tok.clone()),
-            Token::LParen => result.push(tok.clone()),
-            Token::RParen => result.push(tok.clone()),
-            Token::Comma => result.push(tok.clone()),
-            Token::Expression(_) => result.push(tok.clone()),
-        }
+fn print_results(names: &[String], output: &HashMap<String, u8>, format: Format, width: u32) {
+    match format {
+        Format::Text(value_format) => {
+            for name in names {
+                println!("{}: {}", name, value_format.format(*output.get(name).unwrap_or(&0), width));
+            }
+        },
+        Format::Json => {
+            let fields: Vec<String> = names.iter().map(|name| format!("{}: {}", json_string(name), ValueFormat::Decimal.format(*output.get(name).unwrap_or(&0), width))).collect();
+            println!("{{{}}}", fields.join(", "));
+        },
     }
-    result
 }
 
-fn parse_expressions(tokens: &[Token]) -> Token {
-    // Base cases, we have just an input, or true, or false
-    if tokens.len() == 1 {
-        let tok = tokens.first().unwrap();
-        match tok {
-            Token::Chip(_) => {},
-            Token::Input(x) => return Token::IO(x.split(':').nth(0).unwrap().into(), x.split(':').nth(1).unwrap().into()),
-            Token::Output(_) => {},
-            Token::True => return tok.clone(),
-            Token::False => return tok.clone(),
-            Token::Assign => {},
-            Token::LParen => {},
-            Token::RParen => {},
-            Token::Comma => {},
-            Token::Expression(_) => return tok.clone(),
-            Token::IO(_, _) => {},
-            Token::ChipIO(_, _) => {},
+// Lints a library file, or every file in a directory, without evaluating anything. Problems that
+// would make an eval fail are errors, the rest are warnings.
+fn check(options: &Options, path: &str) -> Result<(), String> {
+    let mut paths = Vec::<String>::new();
+    if Path::new(path).is_dir() {
+        for file in nand_files(Path::new(path)).map_err(|e| format!("Could not read {}: {}", path, e))? {
+            paths.push(file.display().to_string());
         }
     }
-    // TODO: Ensure parens match closing
-    // TODO: Check the number of tokens etc
-    let this_chip = tokens.first().unwrap();
-    let mut input_expressions: Vec<Token> = vec![this_chip.clone()];
-    let mut p_count = 0;
-    let mut current_expression = Vec::<Token>::new();
+    else {
+        paths.push(path.to_string());
+    }
 
-    for tok in tokens {
-        if let Token::LParen = tok {
-            p_count += 1;
-            current_expression.push(tok.clone());
-            if p_count == 1 {
-                // Refresh the current expression
-                current_expression.clear();
+    let mut cpu = ChipEvaluator::new();
+    let mut errors = Vec::<String>::new();
+    let mut warnings = Vec::<String>::new();
+    // The file each chip came from, as a file included by several others is read each time
+    let mut origins = HashMap::<String, PathBuf>::new();
+    for path in &paths {
+        let chips = match read_library(Path::new(path)) {
+            Ok(chips) => chips,
+            // Syntax errors already name the file they are in, which may be one the file includes
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                errors.push(e.to_string());
+                continue;
+            },
+            Err(e) => return Err(format!("Could not read {}: {}", path, e)),
+        };
+        for (origin, chip_name, code) in &chips {
+            match origins.insert(chip_name.clone(), origin.clone()) {
+                Some(previous) if previous == *origin => continue,
+                Some(_) => errors.push(format!("{}: chip {} is defined more than once", origin.display(), chip_name)),
+                None => {},
             }
-            continue;
-        }
-
-        if let Token::RParen = tok {
-            p_count -= 1;
-            current_expression.push(tok.clone());
-            if p_count == 0 {
-                // Refresh the current expression
-                input_expressions.push(parse_expressions(&current_expression));
-                current_expression.clear();
+            for name in statement_outputs(code) {
+                let count = code.iter().filter(|tok| matches!(tok, Token::Output(out) if *out == name)).count();
+                if count > 1 {
+                    warnings.push(format!("{}: {} is assigned {} times, the last assignment wins", chip_name, name, count));
+                }
             }
-            continue;
+            cpu.load_chip(chip_name, code);
+            log(options, Verbosity::Verbose, &format!("Loaded chip {} from {}", chip_name, origin.display()));
         }
+    }
 
-        if p_count == 1 {
-            // We are on the current chip's input level
-            if let Token::Comma = tok {
-                // We can flush the current expression
-                input_expressions.push(parse_expressions(&current_expression));
-                current_expression.clear();
-                continue;
+    if let Err(missing) = cpu.resolve() {
+        errors.push(format!("Unknown chips referenced: {}", missing.join(", ")));
+    }
+    match cpu.topological_order() {
+        Ok(chip_names) => {
+            for chip_name in &chip_names {
+                errors.extend(cpu.check_calls(chip_name).unwrap_or_default());
             }
-        }
-
-        // Otherwise we can add to the current expression if it is inside the chip's parentheses (p_count > 0)
-        if p_count > 0 {
-            current_expression.push(tok.clone());
-        }
+        },
+        Err(cycle) => errors.push(cycle.to_string()),
     }
+    warnings.extend(cpu.check());
 
-    if input_expressions.len() == 1 {
-        let tok = input_expressions.first().unwrap();
-        if let Token::Input(x) = tok {
-            return Token::IO(x.split(':').nth(0).unwrap().into(), x.split(':').nth(1).unwrap().into());
-        }
+    for error in &errors {
+        eprintln!("Error: {}", error);
     }
-
-    // We can now return an expression in the form <CHIP, Inputs>
-    return Token::Expression(input_expressions);
-}
-
-fn parse(code: &str) -> Vec<Token> {
-    lex_final(&lex2(&lex(&tokenize(&code))))
-}
-
-fn get_first_output(out: &HashMap<String, u8>) -> u8 {
-    *out.get(out.keys().nth(0).unwrap()).unwrap_or(&0)
-}
-
-trait Executable {
-    fn eval(&self, code: Vec<Token>, inputs: &HashMap<String, u8>) -> HashMap<String, u8>;
-}
-
-struct ChipEvaluator {
-    chips: HashMap<String, Vec<Token>>
+    for warning in &warnings {
+        log(options, Verbosity::Normal, &format!("Warning: {}", warning));
+    }
+    let summary = format!("Checked {} files: {} errors, {} warnings", paths.len(), errors.len(), warnings.len());
+    if !errors.is_empty() {
+        return Err(summary);
+    }
+    log(options, Verbosity::Normal, &summary);
+    Ok(())
 }
 
-impl ChipEvaluator {
-    fn new() -> Self {
-        Self {chips: HashMap::new()}
+fn run(options: &Options) -> Result<(), String> {
+    if let Some(path) = &options.check {
+        return check(options, path);
     }
-
-    fn load_chip(&mut self, chip_name: &str, code: &Vec<Token>)  {
-        self.chips.insert(chip_name.to_string(), code.clone());
+    let mut cpu = ChipEvaluator::new();
+    cpu.set_width(options.width);
+    for path in &options.libraries {
+        // Syntax errors already name the file they are in, which may be one the library includes
+        let chip_names = cpu.load_file(path).map_err(|e| match e.kind() {
+            io::ErrorKind::InvalidData => e.to_string(),
+            _ => format!("Could not read {}: {}", path, e),
+        })?;
+        for chip_name in &chip_names {
+            log(options, Verbosity::Verbose, &format!("Loaded chip {} from {}", chip_name, path));
+        }
+    }
+    if let Err(missing) = cpu.resolve() {
+        return Err(format!("Unknown chips referenced: {}", missing.join(", ")));
+    }
+    for warning in cpu.check() {
+        log(options, Verbosity::Normal, &format!("Warning: {}", warning));
     }
-}
 
-fn NAND(inputs: &HashMap<String, u8>) ->  HashMap<String,u8> {
-    let mut output: HashMap<String,u8> = HashMap::new();
-    let result = !(*inputs.get("a").unwrap_or(&0) & *inputs.get("b").unwrap_or(&0));
-    output.insert(String::from("out"), result);
-    output
-}
+    let mut inputs = HashMap::<String, u8>::new();
+    for (name, value) in &options.inputs {
+        match lex_literal(value).and_then(|literal| cpu.literal(&literal).ok().flatten()) {
+            Some(value) => inputs.insert(name.clone(), value),
+            None => return Err(format!("Invalid value for {}: {}", name, value)),
+        };
+    }
 
-impl Executable for ChipEvaluator {
-    fn eval(&self, code: Vec<Token>, inputs: &HashMap<String, u8>) -> HashMap<String, u8> {
+    if let Some(expression) = &options.expression {
+        if options.program.is_some() {
+            return Err(String::from("--eval and a program file cannot be used together"));
+        }
         let mut output = HashMap::<String, u8>::new();
-        
-        let mut token_iter = code.iter();
-        let mut current_token = token_iter.next();
-        let mut current_out_name: String = String::new();
-        while !current_token.is_none() {
-            // Handle case of outputting
-            match current_token.unwrap()  {
-                Token::Chip(_) => {},
-                Token::ChipIO(_, _) => {},
-                Token::Input(_) => todo!(),
-                Token::IO(_, _) => todo!(),
-                Token::Output(out) => {current_out_name = out.clone();},
-                Token::True => {output.insert(String::from("OUT"), 1 as u8);},
-                Token::False => {output.insert(String::from("OUT"), 0 as u8);},
-                Token::Assign => {},
-                Token::LParen => {},
-                Token::RParen => {},
-                Token::Comma => {},
-                Token::Expression(e_codes) => {
-                    let mut ec_iter = e_codes.iter();
-                    let e_chip =  ec_iter.next().unwrap();
-                    let mut e_inputs = HashMap::<String,u8>::new();
-                    let mut current_input_param = 'a' as u8;
-                    for input_token in ec_iter {
-                        // Handle IO
-                        if let Token::IO(x, y) = input_token {
-                            e_inputs.insert(x.clone(), *inputs.get(y).unwrap_or(&0));
-                        }
-                        // Handle expressions as inputs to current expression
-                        if let Token::Expression(i_toks)   = input_token {
-                            e_inputs.insert((current_input_param as char).to_string(), get_first_output(&self.eval(i_toks.to_vec(), &inputs)));
-                        }
-
-                        current_input_param += 1;
-                    }
-
-                    // Handle normal CHIPs
-                    if let Token::Chip(chip_name) = e_chip {
-                        // Handle NAND CHIP
-                        if chip_name == "NAND" {
-                            let e_result = get_first_output(&NAND(&e_inputs));
-                            if current_out_name.len() > 0 {
-                                output.insert(current_out_name.clone(), e_result);
-                                current_out_name.clear();
-                            }
-                            else  {
-                                output.insert(String::from("out"), e_result);
-                            }
-                        }
-                        // Handle other chips
-                        else {
-                            let chip_instructions = self.chips.get(chip_name).unwrap();
-                            let e_result =  get_first_output(&self.eval(chip_instructions.to_vec(), &e_inputs));
-                            if current_out_name.len() > 0 {
-                                output.insert(current_out_name.clone(), e_result);
-                                current_out_name.clear();
-                            }
-                            else  {
-                                output.insert(String::from("out"), e_result);
-                            }
-                        }
-                    }
+        output.insert(String::from("out"), cpu.eval_expr(expression, &inputs).map_err(|e| e.to_string())?);
+        print_results(&[String::from("out")], &output, options.format, cpu.width());
+        return Ok(());
+    }
 
-                    // Handle CHIPIO chips
-                    else if let Token::ChipIO(chip_name, chip_out) = e_chip {
-                        // Handle NAND CHIP
-                        if chip_name == "NAND" {
-                            let e_result = *NAND(&e_inputs).get(chip_out).unwrap_or(&0);
-                            if current_out_name.len() > 0 {
-                                output.insert(current_out_name.clone(), e_result);
-                                current_out_name.clear();
-                            }
-                            else  {
-                                output.insert(String::from("out"), e_result);
-                            }
-                        }
-                        // Handle other chips
-                        else {
-                            let chip_instructions = self.chips.get(chip_name).unwrap();
-                            let e_result =  *self.eval(chip_instructions.to_vec(), &e_inputs).get(chip_out).unwrap();
-                            if current_out_name.len() > 0 {
-                                output.insert(current_out_name.clone(), e_result);
-                                current_out_name.clear();
-                            }
-                            else  {
-                                output.insert(String::from("out"), e_result);
-                            }
-                        }
-                    }
-                },
+    let program = match &options.program {
+        Some(path) => fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?,
+        None => {
+            // No program given, show off a single NAND
+            if inputs.is_empty() {
+                inputs.insert(String::from("a"), 0b11111111);
+                inputs.insert(String::from("b"), 0b01010101);
             }
+            if let Format::Text(value_format) = options.format {
+                let result = get_first_output(&cpu.eval(parse("OUT = NAND(a: a, b: b)").map_err(|e| e.to_string())?, &inputs).map_err(|e| e.to_string())?);
+                println!("Result of NAND on 1 and 2: {}", value_format.format(result, cpu.width()));
+                return Ok(());
+            }
+            String::from("OUT = NAND(a: a, b: b)")
+        },
+    };
+
+    let start = Instant::now();
+    let code = parse(&program).map_err(|e| e.to_string())?;
+    log(options, Verbosity::Normal, &format!("Parsed in {:?}", start.elapsed()));
+    if options.dump_ast {
+        println!("{:#?}", code);
+        return Ok(());
+    }
 
-
-            current_token = token_iter.next();
+    let names = statement_outputs(&code);
+    for name in &names {
+        let count = code.iter().filter(|tok| matches!(tok, Token::Output(out) if out == name)).count();
+        if count > 1 {
+            log(options, Verbosity::Normal, &format!("Warning: {} is assigned {} times, the last assignment wins", name, count));
         }
-
-        output
     }
-}
 
+    let start = Instant::now();
+    let output = cpu.eval(code.clone(), &inputs).map_err(|e| e.to_string())?;
+    log(options, Verbosity::Normal, &format!("Evaluated in {:?}", start.elapsed()));
+    print_results(&names, &output, options.format, cpu.width());
+    Ok(())
+}
 
 fn main() {
-    // println!("{:#?}", lex2(&lex(&tokenize("// This is a comment\nOUT1 = NAND(a, b)\nXOR=AND(OR(A,B), NAND(A,B))"))));
-    // println!("{:?}", parse("OUT = NAND.out1(a: a, OR(b: b,c: c))\nOUT2=XOR(a: x,b: y)"));
-    let cpu = ChipEvaluator::new();
-    let mut inputs = HashMap::<String, u8>::new();
-    inputs.insert(String::from("a"), 0b11111111);
-    inputs.insert(String::from("b"), 0b01010101);
-    println!("Result of NAND on 1 and 2: {:#08b}", get_first_output(&cpu.eval(parse("OUT = NAND(a: a, b: b)"), &inputs)));
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, usage());
+            process::exit(2);
+        },
+    };
+    if let Err(e) = run(&options) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
 }
-
-/*
-CHIP_A
-INPUTS: I1, I2, ..., IN
-OUTPUTS: O1, O2, ..., ON
-
-CHIP_B
-INPUTS: X1, X2, ..., XN
-OUTPUTS: Y1, Y2, ..., YN
-
-CHIP_B Example Def: Y1 = CHIP_A.O2(I1:X1, I2:X2, ...)
-*/
\ No newline at end of file
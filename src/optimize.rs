@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use crate::{Call, ChipEvaluator, EvalError, Executable, Gate, Netlist, Signal};
+
+impl ChipEvaluator {
+    // Keeps only the requested outputs, dropping every gate and call that none of them depend on
+    pub fn prune(&self, netlist: &Netlist, outputs: &[&str]) -> Netlist {
+        let kept_outputs: Vec<(String, Signal)> = netlist.outputs.iter().filter(|(name, _)| outputs.contains(&name.as_str())).cloned().collect();
+
+        // Walk back from the outputs marking everything in their fan-in
+        let mut live_gates = vec![false; netlist.gates.len()];
+        let mut live_calls = vec![false; netlist.calls.len()];
+        let mut pending: Vec<&Signal> = netlist.outputs.iter().filter(|(name, _)| outputs.contains(&name.as_str())).map(|(_, signal)| signal).collect();
+        while let Some(signal) = pending.pop() {
+            match signal {
+                Signal::Gate(i) if !live_gates[*i] => {
+                    live_gates[*i] = true;
+                    pending.push(&netlist.gates[*i].a);
+                    pending.push(&netlist.gates[*i].b);
+                },
+                Signal::Call(i) if !live_calls[*i] => {
+                    live_calls[*i] = true;
+                    pending.extend(netlist.calls[*i].inputs.iter().map(|(_, signal)| signal));
+                },
+                _ => {},
+            }
+        }
+
+        // Surviving gates and calls keep their order, so they still only refer to earlier ones
+        let mut gate_index = HashMap::<usize, usize>::new();
+        for (i, _) in live_gates.iter().enumerate().filter(|(_, live)| **live) {
+            gate_index.insert(i, gate_index.len());
+        }
+        let mut call_index = HashMap::<usize, usize>::new();
+        for (i, _) in live_calls.iter().enumerate().filter(|(_, live)| **live) {
+            call_index.insert(i, call_index.len());
+        }
+        let remap = |signal: &Signal| -> Option<Signal> {
+            match signal {
+                Signal::Gate(i) => gate_index.get(i).map(|i| Signal::Gate(*i)),
+                Signal::Call(i) => call_index.get(i).map(|i| Signal::Call(*i)),
+                signal => Some(signal.clone()),
+            }
+        };
+
+        let gates: Vec<Gate> = netlist.gates.iter().enumerate().filter(|(i, _)| live_gates[*i]).map(|(_, gate)| {
+            Gate { name: gate.name.clone(), a: remap(&gate.a).unwrap(), b: remap(&gate.b).unwrap() }
+        }).collect();
+        let calls: Vec<Call> = netlist.calls.iter().enumerate().filter(|(i, _)| live_calls[*i]).map(|(_, call)| {
+            Call {
+                name: call.name.clone(),
+                chip: call.chip.clone(),
+                output: call.output.clone(),
+                inputs: call.inputs.iter().map(|(name, signal)| (name.clone(), remap(signal).unwrap())).collect(),
+                after: live_gates[..call.after].iter().filter(|live| **live).count(),
+            }
+        }).collect();
+        let subchips = netlist.subchips.iter().filter(|(chip_name, _)| calls.iter().any(|call| call.chip == **chip_name)).map(|(chip_name, subchip)| (chip_name.clone(), subchip.clone())).collect();
+        let outputs = kept_outputs.iter().map(|(name, signal)| (name.clone(), remap(signal).unwrap())).collect();
+        let probes = netlist.probes.iter().filter_map(|(name, signal)| remap(signal).map(|signal| (name.clone(), signal))).collect();
+
+        Netlist { gates, calls, subchips, outputs, probes }
+    }
+
+    // Evaluates only the gates the wanted outputs depend on, through the pruned netlist. Chips
+    // depending on native or missing chips can only be run by the tree walk, which computes every
+    // output.
+    pub fn eval_outputs(&self, chip_name: &str, inputs: &HashMap<String, u8>, wanted: &[&str]) -> Result<HashMap<String, u8>, EvalError> {
+        let code = self.chips.get(chip_name).ok_or_else(|| EvalError::UnknownChip(chip_name.to_string()))?;
+        match self.flatten_for_eval(chip_name)? {
+            Some(netlist) => Ok(self.prune(&netlist, wanted).eval(inputs)),
+            None => {
+                let mut result = self.eval(code.clone(), inputs).map_err(|e| e.in_chip(chip_name))?;
+                result.retain(|name, _| wanted.contains(&name.as_str()));
+                Ok(result)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CycleError, parse, tests::copy_native};
+
+    #[test]
+    fn eval_outputs_reports_cycles() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("A\nINPUTS: a\nout = B(a)\n\nB\nINPUTS: a\nout = A(a)\n").unwrap();
+        let inputs = HashMap::from([(String::from("a"), 1)]);
+        let cycle = CycleError { chips: vec![String::from("A"), String::from("B"), String::from("A")] };
+        assert_eq!(cpu.eval_outputs("A", &inputs, &["out"]), Err(EvalError::Cycle(cycle)));
+    }
+
+    #[test]
+    fn eval_outputs_runs_natives_through_the_tree_walk() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("T\nINPUTS: a\nOUTPUTS: x, y\nx = COPY(a)\ny = NOT(a)\n").unwrap();
+        cpu.register_native("COPY", copy_native());
+        let inputs = HashMap::from([(String::from("a"), 0b1010)]);
+        assert_eq!(cpu.eval_outputs("T", &inputs, &["x"]).unwrap(), HashMap::from([(String::from("x"), 0b1010)]));
+    }
+
+    #[test]
+    fn prune_drops_gates_only_feeding_unused_outputs() {
+        let mut cpu = ChipEvaluator::new();
+        cpu.load_library("T\nINPUTS: a, b\nOUTPUTS: x, y\nx = NAND(a, b)\ny = NAND(NAND(a, a), b)\n").unwrap();
+        let netlist = cpu.flatten("T").unwrap();
+        assert_eq!(netlist.gates.len(), 3);
+        let pruned = cpu.prune(&netlist, &["x"]);
+        assert_eq!(pruned.gates.len(), 1);
+        assert_eq!(pruned.outputs.iter().map(|(name, _)| name.as_str()).collect::<Vec<&str>>(), ["x"]);
+        let inputs = HashMap::from([(String::from("a"), 0b1100), (String::from("b"), 0b1010)]);
+        assert_eq!(pruned.eval(&inputs), HashMap::from([(String::from("x"), !0b1000)]));
+    }
+
+    #[test]
+    fn eval_outputs_only_runs_the_wanted_fan_in() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("T\nINPUTS: a, b\nOUTPUTS: small, big\nsmall = NAND(a, b)\nbig = ADD8.s7(a, b, a, b, a, b, a, b, b, a, b, a, b, a, b, a)\n").unwrap();
+        let inputs = HashMap::from([(String::from("a"), 0b1100), (String::from("b"), 0b1010)]);
+        assert_eq!(cpu.eval_outputs("T", &inputs, &["small"]).unwrap(), HashMap::from([(String::from("small"), !0b1000)]));
+        let all = cpu.eval(parse("small = T.small(a, b)\nbig = T.big(a, b)").unwrap(), &inputs).unwrap();
+        assert_eq!(cpu.eval_outputs("T", &inputs, &["big"]).unwrap()["big"], all["big"]);
+
+        let netlist = cpu.flatten("T").unwrap();
+        assert_eq!(cpu.prune(&netlist, &["small"]).gates.len(), 1);
+        assert!(cpu.prune(&netlist, &["big"]).gates.len() < netlist.gates.len());
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+
+use crate::{ChipEvaluator, EvalError, Netlist};
+
+impl ChipEvaluator {
+    // Reads a signal inside a chip by its path through the flattened instances, e.g.
+    // `TOP.ADDER0.XOR1.out` for an output of a sub-chip or `TOP.ADDER0.n3` for a single gate.
+    // Instances are named <CHIP><k> in the order their parent uses them, as in gate names.
+    pub fn probe(&self, path: &str, inputs: &HashMap<String, u8>) -> Result<u8, EvalError> {
+        let unknown = || EvalError::UnknownSignal(path.to_string());
+        let (instance, name) = path.rsplit_once('.').ok_or_else(unknown)?;
+        let chip_name = instance.split('.').next().unwrap();
+        let netlist = self.flatten(chip_name)?;
+        let instance = instance.replace('.', "_");
+        let signal = if instance == chip_name {
+            netlist.outputs.iter().find(|(output, _)| output == name).map(|(_, signal)| signal)
+        }
+        else {
+            let probe = format!("{}.{}", instance, name);
+            netlist.probes.iter().find(|(probe_name, _)| *probe_name == probe).map(|(_, signal)| signal)
+        };
+        let (values, calls) = netlist.settle(inputs);
+        match signal {
+            Some(signal) => Ok(Netlist::value_of(signal, inputs, &values, &calls)),
+            // Otherwise a gate, named <instance>_n<index>
+            None => {
+                let gate_name = format!("{}_{}", instance, name);
+                let index = netlist.gates.iter().position(|gate| gate.name == gate_name).ok_or_else(unknown)?;
+                Ok(values[index])
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::copy_native;
+
+    #[test]
+    fn probing_through_a_native_chip_is_an_error() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("T\nINPUTS: a\nout = NOT(COPY(a))\n").unwrap();
+        cpu.register_native("COPY", copy_native());
+        let inputs = HashMap::from([(String::from("a"), 1)]);
+        assert_eq!(cpu.probe("T.out", &inputs), Err(EvalError::CannotFlattenNative(String::from("COPY"))));
+        assert_eq!(cpu.probe("MISSING.out", &inputs), Err(EvalError::UnknownChip(String::from("MISSING"))));
+    }
+
+    #[test]
+    fn probes_reach_signals_two_levels_down() {
+        let cpu = ChipEvaluator::with_stdlib();
+        let inputs = HashMap::from([(String::from("a"), 0b1100), (String::from("b"), 0b1010), (String::from("c"), 0b0110)]);
+        // carry = OR(AND(a, b), AND(s, c)) uses instances AND2, AND3 and OR4, and AND is NOT(NAND(a, b))
+        assert_eq!(cpu.probe("FULLADDER.AND2.NOT0.out", &inputs).unwrap(), 0b1000);
+        assert_eq!(cpu.probe("FULLADDER.AND3.NOT0.out", &inputs).unwrap(), 0b0110);
+        assert_eq!(cpu.probe("FULLADDER.AND2.NOT0.n0", &inputs).unwrap(), 0b1000);
+        assert_eq!(cpu.probe("FULLADDER.AND2.n0", &inputs).unwrap(), !0b1000);
+        assert_eq!(cpu.probe("FULLADDER.carry", &inputs).unwrap(), 0b1110);
+        assert_eq!(cpu.probe("FULLADDER.AND2.NOT1.out", &inputs), Err(EvalError::UnknownSignal(String::from("FULLADDER.AND2.NOT1.out"))));
+    }
+}
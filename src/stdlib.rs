@@ -0,0 +1,35 @@
+use crate::ChipEvaluator;
+
+// NOT, AND, OR, XOR, MUX and the adders, written in NandScript
+pub const STDLIB: &str = include_str!("stdlib.nand");
+
+impl ChipEvaluator {
+    // An evaluator with the standard chips already loaded
+    pub fn with_stdlib() -> Self {
+        let mut cpu = ChipEvaluator::new();
+        cpu.load_library(STDLIB).expect("The standard library should parse");
+        cpu
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn add8_wraps_like_u8() {
+        let cpu = ChipEvaluator::with_stdlib();
+        let pairs: [(u8, u8); 6] = [(0, 0), (1, 2), (100, 27), (200, 100), (255, 1), (0xAA, 0x55)];
+        // Each pair runs in its own lane
+        let cases: Vec<HashMap<String, bool>> = pairs.iter().map(|(a, b)| {
+            (0..8).flat_map(|i| [(format!("a{}", i), (a >> i) & 1 == 1), (format!("b{}", i), (b >> i) & 1 == 1)]).collect()
+        }).collect();
+        for ((a, b), outputs) in pairs.iter().zip(cpu.eval_lanes("ADD8", &cases).unwrap()) {
+            let sum = (0..8).fold(0u8, |value, i| value | ((outputs[&format!("s{}", i)] as u8) << i));
+            assert_eq!(sum, a.wrapping_add(*b), "{} + {}", a, b);
+            assert_eq!(outputs["carry"], a.checked_add(*b).is_none(), "{} + {}", a, b);
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::{collections::HashMap, io::{self, BufRead, Write}};
+
+use crate::{ChipEvaluator, EvalError, lex_literal};
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl ChipEvaluator {
+    // Evaluates a chip once per line of `reader`, writing one line of outputs per line read.
+    // Lines hold `name=value` pairs, or bare values bound in the order of the chip's inputs.
+    // Blank lines and `//` comments are skipped. Returns how many vectors were evaluated.
+    pub fn eval_stream(&self, chip_name: &str, reader: impl BufRead, mut writer: impl Write) -> io::Result<usize> {
+        let code = self.chips.get(chip_name).ok_or_else(|| invalid_data(EvalError::UnknownChip(chip_name.to_string()).to_string()))?;
+        let input_names = self.inputs_of(chip_name);
+        let output_names = self.outputs_of(chip_name);
+
+        let mut count = 0;
+        for (line_number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.split("//").next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut inputs = HashMap::<String, u8>::new();
+            for (position, field) in line.split(|c: char| c.is_whitespace() || c == ',').filter(|x| !x.is_empty()).enumerate() {
+                let (name, value) = match field.split_once('=') {
+                    Some((name, value)) => (name.to_string(), value),
+                    None => match input_names.get(position) {
+                        Some(name) => (name.clone(), field),
+                        None => return Err(invalid_data(format!("Line {}: no input declared at position {}", line_number + 1, position + 1))),
+                    },
+                };
+                match lex_literal(value).and_then(|literal| self.literal(&literal).ok().flatten()) {
+                    Some(value) => inputs.insert(name, value),
+                    None => return Err(invalid_data(format!("Line {}: invalid value for {}: {}", line_number + 1, name, value))),
+                };
+            }
+
+            let output = self.eval_body(code, &inputs, 0, &mut None).map_err(|e| e.in_chip(chip_name)).map_err(|e| invalid_data(format!("Line {}: {}", line_number + 1, e)))?;
+            let fields: Vec<String> = output_names.iter().map(|name| format!("{}={}", name, output.get(name).unwrap_or(&0) & self.mask())).collect();
+            writeln!(writer, "{}", fields.join(" "))?;
+            count += 1;
+        }
+        writer.flush()?;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streams_only_write_declared_outputs() {
+        let cpu = ChipEvaluator::with_stdlib();
+        let mut written = Vec::<u8>::new();
+        let count = cpu.eval_stream("FULLADDER", "1 1 0\n// carry in\n\na=0 b=1 c=1\n".as_bytes(), &mut written).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(String::from_utf8(written).unwrap(), "sum=0 carry=255\nsum=0 carry=255\n");
+    }
+}
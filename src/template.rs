@@ -0,0 +1,109 @@
+use crate::{ChipEvaluator, EvalError, NandError, parse};
+
+// Replaces the placeholders of one line: `{N}` with the size and `{i}` or `{i+k}` with the index.
+// Also tells whether the line has any index placeholder.
+fn substitute(line: &str, index: usize, size: usize) -> Result<(String, bool), NandError> {
+    let mut result = String::new();
+    let mut indexed = false;
+    let mut rest = line;
+    while let Some(start) = rest.find('{') {
+        result += &rest[..start];
+        let end = rest[start..].find('}').ok_or_else(|| NandError::Parse(format!("Unclosed template parameter in: {}", line.trim())))? + start;
+        let parameter: String = rest[start + 1..end].chars().filter(|c| !c.is_whitespace()).collect();
+        let value = if parameter == "N" {
+            size
+        }
+        else if parameter == "i" {
+            indexed = true;
+            index
+        }
+        else if let Some(offset) = parameter.strip_prefix("i+").and_then(|x| x.parse::<usize>().ok()) {
+            indexed = true;
+            index + offset
+        }
+        else {
+            return Err(NandError::Parse(format!("Unknown template parameter {{{}}}", parameter)));
+        };
+        result += &value.to_string();
+        rest = &rest[end + 1..];
+    }
+    result += rest;
+    Ok((result, indexed))
+}
+
+// Expands a template body for one size. Statement lines using `{i}` are repeated for i from 0
+// to size - 1, and so are the names in INPUTS:/OUTPUTS: lines that use it. `{N}` is the size.
+pub fn expand_template(source: &str, size: usize) -> Result<String, NandError> {
+    let mut result = String::new();
+    for line in source.lines() {
+        let declaration = ["INPUTS:", "OUTPUTS:"].iter().find(|prefix| line.trim_start().starts_with(*prefix));
+        if let Some(prefix) = declaration {
+            let list = line.trim_start()[prefix.len()..].split("//").next().unwrap();
+            let mut names = Vec::<String>::new();
+            for name in list.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+                let (expanded, indexed) = substitute(name, 0, size)?;
+                if !indexed {
+                    names.push(expanded);
+                    continue;
+                }
+                for index in 0..size {
+                    names.push(substitute(name, index, size)?.0);
+                }
+            }
+            result += &format!("{} {}\n", prefix, names.join(", "));
+            continue;
+        }
+
+        let (expanded, indexed) = substitute(line, 0, size)?;
+        if !indexed {
+            result += &expanded;
+            result.push('\n');
+            continue;
+        }
+        // Each copy is a single line, so a repeated statement can not span lines
+        let copies: Vec<String> = (0..size).map(|index| substitute(line, index, size).map(|x| x.0)).collect::<Result<_, _>>()?;
+        result += &copies.join(" ");
+        result.push('\n');
+    }
+    Ok(result)
+}
+
+impl ChipEvaluator {
+    // Keeps a chip body with `{i}` and `{N}` parameters, to be turned into chips by instantiate
+    pub fn load_template(&mut self, template_name: &str, source: &str) {
+        self.templates.insert(template_name.to_string(), source.to_string());
+    }
+
+    // Loads the template expanded for `size` as the chip <template><size>, e.g. AND8, and returns its name
+    pub fn instantiate(&mut self, template_name: &str, size: usize) -> Result<String, NandError> {
+        let source = self.templates.get(template_name).ok_or_else(|| EvalError::UnknownChip(template_name.to_string()))?;
+        let code = parse(&expand_template(source, size)?)?;
+        let chip_name = format!("{}{}", template_name, size);
+        self.load_chip(&chip_name, &code);
+        Ok(chip_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn templates_instantiate_at_each_size() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_template("ANDN", "INPUTS: a{i}, b{i}\nOUTPUTS: out{i}\nout{i} = AND(a{i}, b{i})\n");
+        assert_eq!(cpu.instantiate("ANDN", 2).unwrap(), "ANDN2");
+        assert_eq!(cpu.instantiate("ANDN", 4).unwrap(), "ANDN4");
+        assert_eq!(cpu.inputs_of("ANDN2"), vec!["a0", "a1", "b0", "b1"]);
+        assert_eq!(cpu.outputs_of("ANDN4"), vec!["out0", "out1", "out2", "out3"]);
+
+        let inputs: HashMap<String, bool> = (0..4).flat_map(|i| [(format!("a{}", i), true), (format!("b{}", i), i % 2 == 0)]).collect();
+        let two = cpu.eval_bools("ANDN2", &inputs).unwrap();
+        assert_eq!(two, HashMap::from([(String::from("out0"), true), (String::from("out1"), false)]));
+        let four = cpu.eval_bools("ANDN4", &inputs).unwrap();
+        assert_eq!((0..4).map(|i| four[&format!("out{}", i)]).collect::<Vec<bool>>(), vec![true, false, true, false]);
+        assert_eq!(cpu.instantiate("MISSING", 2), Err(NandError::Eval(EvalError::UnknownChip(String::from("MISSING")))));
+    }
+}
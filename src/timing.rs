@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use crate::{ChipEvaluator, EvalError, Netlist, Signal};
+
+impl Netlist {
+    // Unit-delay simulation: starting from the settled state for `from`, the inputs switch to `to`
+    // at time 0 and every NAND takes one time unit to follow its inputs. Returns each change of
+    // an output as (time, output name, value), so transient glitches show up as extra events.
+    pub fn simulate(&self, from: &HashMap<String, u8>, to: &HashMap<String, u8>) -> Vec<(usize, String, u8)> {
+        let mut events = Vec::<(usize, String, u8)>::new();
+        // Calls to sub-chips are treated as settling instantly
+        let (mut values, mut calls) = self.settle(from);
+        let mut last_outputs: Vec<u8> = self.outputs.iter().map(|(_, signal)| Netlist::value_of(signal, from, &values, &calls)).collect();
+
+        // Outputs wired straight to an input or constant change immediately
+        calls = self.call_values(to, &values);
+        for (i, (name, signal)) in self.outputs.iter().enumerate() {
+            let value = Netlist::value_of(signal, to, &values, &calls);
+            if value != last_outputs[i] {
+                events.push((0, name.clone(), value));
+                last_outputs[i] = value;
+            }
+        }
+
+        // A combinational netlist settles after at most one step per gate
+        for time in 1..=self.gates.len() {
+            let next: Vec<u8> = self.gates.iter().map(|gate| {
+                !(Netlist::value_of(&gate.a, to, &values, &calls) & Netlist::value_of(&gate.b, to, &values, &calls))
+            }).collect();
+            let next_calls = self.call_values(to, &next);
+            let changed = next != values || next_calls != calls;
+            values = next;
+            calls = next_calls;
+
+            for (i, (name, signal)) in self.outputs.iter().enumerate() {
+                let value = Netlist::value_of(signal, to, &values, &calls);
+                if value != last_outputs[i] {
+                    events.push((time, name.clone(), value));
+                    last_outputs[i] = value;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        events
+    }
+
+    // Static timing: the worst-case time each output settles after the inputs change at time 0,
+    // with every NAND taking `nand_delay`. Calls take as long as the slowest path through the
+    // called chip's output, counted from their latest input.
+    pub fn arrival_times(&self, nand_delay: f64) -> HashMap<String, f64> {
+        let mut gates = vec![None; self.gates.len()];
+        let mut calls = vec![None; self.calls.len()];
+        self.outputs.iter().map(|(name, signal)| (name.clone(), self.arrival(signal, nand_delay, &mut gates, &mut calls))).collect()
+    }
+
+    fn arrival(&self, signal: &Signal, nand_delay: f64, gates: &mut [Option<f64>], calls: &mut [Option<f64>]) -> f64 {
+        match signal {
+            Signal::Input(_) | Signal::Const(_) => 0.0,
+            Signal::Gate(i) => {
+                if let Some(time) = gates[*i] {
+                    return time;
+                }
+                let gate = &self.gates[*i];
+                let time = nand_delay + self.arrival(&gate.a, nand_delay, gates, calls).max(self.arrival(&gate.b, nand_delay, gates, calls));
+                gates[*i] = Some(time);
+                time
+            },
+            Signal::Call(i) => {
+                if let Some(time) = calls[*i] {
+                    return time;
+                }
+                let call = &self.calls[*i];
+                let latest_input = call.inputs.iter().map(|(_, signal)| self.arrival(signal, nand_delay, gates, calls)).fold(0.0, f64::max);
+                let through = self.subchips[&call.chip].arrival_times(nand_delay).get(&call.output).copied().unwrap_or(0.0);
+                calls[*i] = Some(latest_input + through);
+                latest_input + through
+            },
+        }
+    }
+}
+
+impl ChipEvaluator {
+    pub fn simulate_delays(&self, chip_name: &str, from: &HashMap<String, u8>, to: &HashMap<String, u8>) -> Result<Vec<(usize, String, u8)>, EvalError> {
+        Ok(self.flatten(chip_name)?.simulate(from, to))
+    }
+
+    // Worst-case delay from the inputs to each output of the chip, see Netlist::arrival_times.
+    // Locals are left out when the chip declares its outputs.
+    pub fn timing_report(&self, chip_name: &str, nand_delay: f64) -> Result<HashMap<String, f64>, EvalError> {
+        let outputs = self.outputs_of(chip_name);
+        let mut times = self.flatten(chip_name)?.arrival_times(nand_delay);
+        times.retain(|name, _| outputs.contains(name));
+        Ok(times)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timing_of_a_chip_calling_a_missing_chip_is_an_error() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("T\nINPUTS: a\nout = NOT(MISSING(a))\n").unwrap();
+        let unknown = EvalError::UnknownChip(String::from("MISSING"));
+        assert_eq!(cpu.timing_report("T", 1.0), Err(unknown.clone()));
+        assert_eq!(cpu.simulate_delays("T", &HashMap::new(), &HashMap::new()), Err(unknown));
+    }
+
+    #[test]
+    fn static_hazards_show_up_as_glitches() {
+        // A mux holding 1 on both data inputs, whose select path through the inverter is one NAND slower
+        let mut cpu = ChipEvaluator::new();
+        cpu.load_library("HAZARD\nINPUTS: a, b, s\nout = NAND(NAND(a, s), NAND(b, NAND(s, s)))\n").unwrap();
+        let from = HashMap::from([(String::from("a"), 0xff), (String::from("b"), 0xff), (String::from("s"), 0xff)]);
+        let mut to = from.clone();
+        to.insert(String::from("s"), 0);
+        assert_eq!(cpu.simulate_delays("HAZARD", &from, &to).unwrap(), vec![(2, String::from("out"), 0), (3, String::from("out"), 0xff)]);
+        // Switching back is hazard free
+        assert_eq!(cpu.simulate_delays("HAZARD", &to, &from).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn two_levels_take_twice_the_gate_delay() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("ONE\nINPUTS: a, b\nout = NAND(a, b)\n\nTWO\nINPUTS: a, b, c\nout = NAND(NAND(a, b), c)\n").unwrap();
+        assert_eq!(cpu.timing_report("ONE", 1.5).unwrap(), HashMap::from([(String::from("out"), 1.5)]));
+        assert_eq!(cpu.timing_report("TWO", 1.5).unwrap(), HashMap::from([(String::from("out"), 3.0)]));
+        // AND is a NAND followed by NOT
+        assert_eq!(cpu.timing_report("AND", 2.0).unwrap()["out"], 4.0);
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use crate::{ChipEvaluator, EvalError, Netlist, Signal};
+
+// The value and valid lanes of a signal. Unbound inputs are invalid in every lane, constants valid.
+fn signal_of(signal: &Signal, inputs: &HashMap<String, u8>, valid: &HashMap<String, u8>, gates: &[(u8, u8)], calls: &[(u8, u8)]) -> (u8, u8) {
+    match signal {
+        Signal::Input(name) => (*inputs.get(name).unwrap_or(&0), *valid.get(name).unwrap_or(&0)),
+        Signal::Const(x) => (*x, 0xff),
+        Signal::Gate(i) => gates[*i],
+        Signal::Call(i) => calls[*i],
+    }
+}
+
+impl Netlist {
+    // Like eval, also giving the lanes of each output that depend only on bound inputs. A NAND
+    // lane is known when both inputs are, or when either one is a known 0.
+    pub fn eval_validity(&self, inputs: &HashMap<String, u8>) -> HashMap<String, (u8, u8)> {
+        let valid: HashMap<String, u8> = inputs.keys().map(|name| (name.clone(), 0xff)).collect();
+        self.eval_validity_with(inputs, &valid)
+    }
+
+    fn eval_validity_with(&self, inputs: &HashMap<String, u8>, valid: &HashMap<String, u8>) -> HashMap<String, (u8, u8)> {
+        let mut gates = Vec::<(u8, u8)>::with_capacity(self.gates.len());
+        let mut calls = Vec::<(u8, u8)>::with_capacity(self.calls.len());
+        for i in 0..=self.gates.len() {
+            while calls.len() < self.calls.len() && self.calls[calls.len()].after == i {
+                let call = &self.calls[calls.len()];
+                let mut call_inputs = HashMap::<String, u8>::new();
+                let mut call_valid = HashMap::<String, u8>::new();
+                for (name, signal) in &call.inputs {
+                    let (value, value_valid) = signal_of(signal, inputs, valid, &gates, &calls);
+                    call_inputs.insert(name.clone(), value);
+                    call_valid.insert(name.clone(), value_valid);
+                }
+                let result = self.subchips[&call.chip].eval_validity_with(&call_inputs, &call_valid);
+                calls.push(*result.get(&call.output).unwrap_or(&(0, 0)));
+            }
+            if let Some(gate) = self.gates.get(i) {
+                let (a, a_valid) = signal_of(&gate.a, inputs, valid, &gates, &calls);
+                let (b, b_valid) = signal_of(&gate.b, inputs, valid, &gates, &calls);
+                gates.push((!(a & b), (a_valid & b_valid) | (a_valid & !a) | (b_valid & !b)));
+            }
+        }
+
+        self.outputs.iter().map(|(name, signal)| (name.clone(), signal_of(signal, inputs, valid, &gates, &calls))).collect()
+    }
+}
+
+impl ChipEvaluator {
+    // Evaluates a chip returning each output as (value, valid lanes), where a lane is valid when
+    // it does not depend on an input missing from `inputs`, which would otherwise read as 0
+    pub fn eval_validity(&self, chip_name: &str, inputs: &HashMap<String, u8>) -> Result<HashMap<String, (u8, u8)>, EvalError> {
+        let mask = self.mask();
+        Ok(self.flatten(chip_name)?.eval_validity(inputs).into_iter().map(|(name, (value, valid))| (name, (value & mask, valid & mask))).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::copy_native;
+
+    #[test]
+    fn validity_through_a_native_chip_is_an_error() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("T\nINPUTS: a\nout = NOT(COPY(a))\n").unwrap();
+        cpu.register_native("COPY", copy_native());
+        let inputs = HashMap::from([(String::from("a"), 1)]);
+        assert_eq!(cpu.eval_validity("T", &inputs), Err(EvalError::CannotFlattenNative(String::from("COPY"))));
+    }
+
+    #[test]
+    fn unbound_inputs_leave_their_lanes_invalid() {
+        let mut cpu = ChipEvaluator::with_stdlib();
+        cpu.load_library("T\nINPUTS: a, b\nOUTPUTS: x, y\nx = AND(a, b)\ny = NOT(a)\n").unwrap();
+        let inputs = HashMap::from([(String::from("a"), 0b00001111)]);
+        let outputs = cpu.eval_validity("T", &inputs).unwrap();
+        // Lanes where a is 0 are 0 whatever b is
+        assert_eq!(outputs["x"], (0, 0b11110000));
+        assert_eq!(outputs["y"], (0b11110000, 0b11111111));
+        let inputs = HashMap::from([(String::from("a"), 0b00001111), (String::from("b"), 0b00000101)]);
+        assert_eq!(cpu.eval_validity("T", &inputs).unwrap()["x"], (0b00000101, 0b11111111));
+    }
+}
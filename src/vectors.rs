@@ -0,0 +1,155 @@
+use std::{collections::HashMap, fs, io, path::Path};
+
+use crate::{ChipEvaluator, EvalError, Executable, TestReport, TestResult, lex_literal};
+
+// One row of a vector file, each value already masked to the width
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestVector {
+    pub inputs: Vec<(String, u8)>,
+    pub outputs: Vec<(String, u8)>,
+}
+
+fn invalid_data(path: &Path, line: usize, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}: {}", path.display(), line, message))
+}
+
+fn lane_bits(values: &[(String, u8)], lane: usize) -> Vec<(&str, u8)> {
+    values.iter().map(|(name, value)| (name.as_str(), value >> lane & 1)).collect()
+}
+
+impl ChipEvaluator {
+    // Reads a vector file, laid out like a printed truth table:
+    //   a b | out
+    //   0 1 | 1
+    // Values are literals, so 1 fills every lane and 0x0f sets the low four. Blank lines and `//`
+    // comments are skipped.
+    pub fn read_vectors(&self, path: impl AsRef<Path>) -> io::Result<Vec<TestVector>> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+        let mut lines = source.lines().enumerate().map(|(index, line)| (index + 1, line.split("//").next().unwrap().trim())).filter(|(_, line)| !line.is_empty());
+
+        let Some((header_line, header)) = lines.next() else {
+            return Err(invalid_data(path, 1, "Missing the header line of input and output names"));
+        };
+        let Some((input_names, output_names)) = header.split_once('|') else {
+            return Err(invalid_data(path, header_line, "Expected a '|' between the input and output names"));
+        };
+        let input_names: Vec<&str> = input_names.split_whitespace().collect();
+        let output_names: Vec<&str> = output_names.split_whitespace().collect();
+
+        let mut vectors = Vec::<TestVector>::new();
+        for (line_number, line) in lines {
+            let mut values = Vec::<u8>::new();
+            for value in line.split_whitespace().filter(|value| *value != "|") {
+                match lex_literal(value).and_then(|literal| self.literal(&literal).ok().flatten()) {
+                    Some(value) => values.push(value & self.mask()),
+                    None => return Err(invalid_data(path, line_number, &format!("Invalid value {}", value))),
+                }
+            }
+            if values.len() != input_names.len() + output_names.len() {
+                return Err(invalid_data(path, line_number, &format!("Expected {} values, found {}", input_names.len() + output_names.len(), values.len())));
+            }
+            vectors.push(TestVector {
+                inputs: input_names.iter().map(|name| name.to_string()).zip(values.iter().copied()).collect(),
+                outputs: output_names.iter().map(|name| name.to_string()).zip(values[input_names.len()..].iter().copied()).collect(),
+            });
+        }
+        Ok(vectors)
+    }
+
+    // Evaluates the chip on each row of a vector file, see read_vectors. Each output of each row
+    // gets a result, a row the chip fails to evaluate fails all of them with the error.
+    pub fn run_vectors(&self, chip_name: &str, path: impl AsRef<Path>) -> io::Result<TestReport> {
+        let mut report = TestReport::default();
+        for (row, vector) in self.read_vectors(path)?.into_iter().enumerate() {
+            let inputs: HashMap<String, u8> = vector.inputs.into_iter().collect();
+            let result = match self.chips.get(chip_name) {
+                Some(code) => self.eval(code.clone(), &inputs).map_err(|e| e.in_chip(chip_name)),
+                None => Err(EvalError::UnknownChip(chip_name.to_string())),
+            };
+            for (output, expected) in vector.outputs {
+                report.results.push(TestResult {
+                    chip: chip_name.to_string(),
+                    index: row + 1,
+                    actual: result.as_ref().map(|values| values.get(&output).copied().unwrap_or(0) & self.mask()).unwrap_or(0),
+                    output: Some(output),
+                    expected,
+                    error: result.as_ref().err().cloned(),
+                });
+            }
+        }
+        Ok(report)
+    }
+
+    // A SystemVerilog testbench driving a module with the chip's name and ports through the vectors,
+    // with an immediate assertion per output checked. Every lane of a row is a case of its own, so
+    // a row written with 0s and 1s is one case and a row like `0x0f 0x3c | 0x0c` is four.
+    pub fn to_sva(&self, chip_name: &str, vectors: &[TestVector]) -> Result<String, EvalError> {
+        if !self.chips.contains_key(chip_name) {
+            return Err(EvalError::UnknownChip(chip_name.to_string()));
+        }
+        let inputs = self.inputs_of(chip_name);
+        let outputs = self.outputs_of(chip_name);
+        let ports: Vec<String> = inputs.iter().chain(&outputs).map(|name| format!(".{}({})", name, name)).collect();
+
+        let mut sva = format!("module {}_tb;\n", chip_name);
+        for name in inputs.iter().chain(&outputs) {
+            sva += &format!("    logic {};\n", name);
+        }
+        sva += &format!("\n    {} dut ({});\n\n    initial begin\n", chip_name, ports.join(", "));
+        for (row, vector) in vectors.iter().enumerate() {
+            let mut cases = Vec::<(Vec<(&str, u8)>, Vec<(&str, u8)>)>::new();
+            for lane in 0..self.width() as usize {
+                let case = (lane_bits(&vector.inputs, lane), lane_bits(&vector.outputs, lane));
+                if !cases.contains(&case) {
+                    cases.push(case);
+                }
+            }
+            for (inputs, outputs) in cases {
+                for (name, bit) in inputs {
+                    sva += &format!("        {} = 1'b{};\n", name, bit);
+                }
+                sva += "        #1;\n";
+                for (name, bit) in outputs {
+                    sva += &format!("        assert ({} === 1'b{}) else $error(\"row {}: {} is %b, expected {}\", {});\n", name, bit, row + 1, name, bit, name);
+                }
+            }
+        }
+        sva += "        $finish;\n    end\nendmodule\n";
+        Ok(sva)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failing_rows_are_reported() {
+        let path = std::env::temp_dir().join(format!("nandscript-vectors-{}.txt", std::process::id()));
+        fs::write(&path, "a b | out  // AND\n0 0 | 0\n0 1 | 0\n1 1 | 0\n\n1 0 | 0\n").unwrap();
+        let report = ChipEvaluator::with_stdlib().run_vectors("AND", &path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(report.passed(), 3);
+        assert_eq!(report.failed(), 1);
+        let failure = report.results.iter().find(|result| !result.passed()).unwrap();
+        assert_eq!((failure.index, failure.output.as_deref(), failure.expected, failure.actual), (3, Some("out"), 0, 0xff));
+    }
+
+    #[test]
+    fn testbenches_assert_every_row() {
+        let cpu = ChipEvaluator::with_stdlib();
+        let row = |a: u8, b: u8, out: u8| TestVector { inputs: vec![(String::from("a"), a), (String::from("b"), b)], outputs: vec![(String::from("out"), out)] };
+        let vectors = [row(0, 0, 0), row(0, 0xff, 0), row(0xff, 0, 0), row(0xff, 0xff, 0xff)];
+        let sva = cpu.to_sva("AND", &vectors).unwrap();
+        assert!(sva.starts_with("module AND_tb;\n"));
+        assert!(sva.contains("    AND dut (.a(a), .b(b), .out(out));\n"));
+        assert_eq!(sva.matches("assert (").count(), vectors.len());
+        for index in 1..=vectors.len() {
+            assert!(sva.contains(&format!("\"row {}: out is %b", index)), "row {}", index);
+        }
+        assert!(sva.contains("        a = 1'b1;\n        b = 1'b1;\n        #1;\n        assert (out === 1'b1)"));
+        assert_eq!(cpu.to_sva("MISSING", &vectors), Err(EvalError::UnknownChip(String::from("MISSING"))));
+    }
+}
@@ -0,0 +1,85 @@
+use crate::{Token, expand_reduce};
+
+// Callbacks for walk_body. Every method does nothing by default, so an analysis only
+// implements the ones it cares about.
+pub trait TokenVisitor {
+    // `name = ...`, before the statement it names
+    fn visit_output(&mut self, _name: &str) {}
+    // Any `CHIP(...)` or `CHIP.out(...)` call, before its chip and arguments are visited
+    fn visit_expression(&mut self, _e_codes: &[Token]) {}
+    // The chip a call goes to, with the output picked by `CHIP.out` if any
+    fn visit_chip(&mut self, _chip_name: &str, _output: Option<&str>) {}
+    // A named `input: source` argument
+    fn visit_io(&mut self, _input: &str, _source: &str) {}
+    // A bare name read, as a positional argument or a whole statement
+    fn visit_input(&mut self, _source: &str) {}
+    fn visit_literal(&mut self, _tok: &Token) {}
+    // A `!`, before the inverted argument or expression is visited
+    fn visit_not(&mut self) {}
+}
+
+// Visits one token and everything nested in it, depth first and in source order
+pub fn walk(tok: &Token, visitor: &mut impl TokenVisitor) {
+    match tok {
+        Token::Output(name) => visitor.visit_output(name),
+        // REDUCE is visited as the calls it stands for
+        Token::Expression(e_codes) => match expand_reduce(e_codes) {
+            Some(folded) => walk(&folded, visitor),
+            None => {
+                visitor.visit_expression(e_codes);
+                for tok in e_codes {
+                    walk(tok, visitor);
+                }
+            },
+        },
+        Token::Chip(chip_name) => visitor.visit_chip(chip_name, None),
+        Token::ChipIO(chip_name, output) => visitor.visit_chip(chip_name, Some(output)),
+        Token::IO(input, source) => visitor.visit_io(input, source),
+        Token::Input(source) => visitor.visit_input(source),
+        Token::True | Token::False | Token::Const(_) => visitor.visit_literal(tok),
+        Token::Not(inner) => {
+            visitor.visit_not();
+            walk(inner, visitor);
+        },
+        Token::Assign | Token::LParen | Token::RParen | Token::Comma | Token::Assert | Token::Inputs(_)
+            | Token::Outputs(_) | Token::DefaultOutput(_) | Token::Doc(_) | Token::Attribute(_, _) | Token::Bang => {},
+    }
+}
+
+// Visits every statement of a chip body
+pub fn walk_body(code: &[Token], visitor: &mut impl TokenVisitor) {
+    for tok in code {
+        walk(tok, visitor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    // Counts the named `input: source` arguments and the chips called
+    #[derive(Default)]
+    struct CountIo {
+        io: usize,
+        chips: usize,
+    }
+
+    impl TokenVisitor for CountIo {
+        fn visit_io(&mut self, _input: &str, _source: &str) {
+            self.io += 1;
+        }
+        fn visit_chip(&mut self, _chip_name: &str, _output: Option<&str>) {
+            self.chips += 1;
+        }
+    }
+
+    #[test]
+    fn visitors_see_nested_and_reduced_arguments() {
+        let code = parse("x = NAND(a: p, !NOT(a: q))\nout = REDUCE(AND, x, ADD.sum(a: r, b: 1), c)").unwrap();
+        let mut count = CountIo::default();
+        walk_body(&code, &mut count);
+        // REDUCE stands for two AND calls
+        assert_eq!((count.io, count.chips), (4, 5));
+    }
+}
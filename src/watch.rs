@@ -0,0 +1,129 @@
+use std::{collections::{HashMap, HashSet}, io, path::{Path, PathBuf}, sync::mpsc::{self, Receiver}, time::Duration};
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{ChipEvaluator, dir::read_dir};
+
+// Watches a directory of `.nand` files, see ChipEvaluator::watch
+pub struct ChipWatcher {
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    // The watched directory, canonicalized like the paths notify reports
+    dir: PathBuf,
+    // The chips each file loaded last time, so chips can be dropped when their file changes or goes
+    files: HashMap<PathBuf, Vec<String>>,
+    debounce: Duration,
+}
+
+impl ChipWatcher {
+    // How long the directory has to stay quiet before changes are reloaded, 50ms by default
+    pub fn set_debounce(&mut self, debounce: Duration) {
+        self.debounce = debounce;
+    }
+}
+
+impl ChipEvaluator {
+    // Loads the directory like load_dir and starts watching it. Call reload_changes to pick up edits.
+    pub fn watch(&mut self, dir: impl AsRef<Path>) -> io::Result<ChipWatcher> {
+        let dir = dir.as_ref().canonicalize()?;
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender).map_err(io::Error::other)?;
+        watcher.watch(&dir, RecursiveMode::NonRecursive).map_err(io::Error::other)?;
+
+        let mut files = HashMap::<PathBuf, Vec<String>>::new();
+        for (path, chip_name, code) in read_dir(&dir)? {
+            self.load_chip(&chip_name, &code);
+            files.entry(path).or_default().push(chip_name);
+        }
+        Ok(ChipWatcher { _watcher: watcher, events, dir, files, debounce: Duration::from_millis(50) })
+    }
+
+    // Reloads the `.nand` files changed since the last call and returns the chips they hold now.
+    // A burst of events, as editors make while saving, is waited out so each file loads once.
+    // Chips of a deleted file are removed. The whole directory is read again like load_dir does, so
+    // a chip defined by two of the files is an InvalidData error and nothing is reloaded.
+    pub fn reload_changes(&mut self, watcher: &mut ChipWatcher) -> io::Result<Vec<String>> {
+        let mut changed = HashSet::<PathBuf>::new();
+        let mut next = watcher.events.try_recv().ok();
+        while let Some(event) = next {
+            let event = event.map_err(io::Error::other)?;
+            changed.extend(event.paths.iter().filter(|path| path.extension().is_some_and(|extension| extension == "nand")).map(|path| canonical(path)));
+            next = watcher.events.recv_timeout(watcher.debounce).ok();
+        }
+        if changed.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let chips = read_dir(&watcher.dir)?;
+        for path in &changed {
+            for chip_name in watcher.files.remove(path).unwrap_or_default() {
+                self.remove_chip(&chip_name);
+            }
+        }
+        let mut chip_names = Vec::<String>::new();
+        for (path, chip_name, code) in chips.into_iter().filter(|(path, _, _)| changed.contains(path)) {
+            self.load_chip(&chip_name, &code);
+            chip_names.push(chip_name.clone());
+            watcher.files.entry(path).or_default().push(chip_name);
+        }
+        Ok(chip_names)
+    }
+}
+
+// A deleted file can no longer be canonicalized, but the directory it was in can
+fn canonical(path: &Path) -> PathBuf {
+    match (path.parent().and_then(|parent| parent.canonicalize().ok()), path.file_name()) {
+        (Some(parent), Some(file_name)) => parent.join(file_name),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use notify::EventKind;
+
+    use super::*;
+
+    // Sends events to the watcher as notify would, with the absolute paths it reports
+    fn simulate(watcher: &mut ChipWatcher) -> mpsc::Sender<notify::Result<Event>> {
+        let (sender, events) = mpsc::channel();
+        watcher.events = events;
+        sender
+    }
+
+    #[test]
+    fn changed_files_reload_through_the_directory() {
+        let dir = std::env::temp_dir().join(format!("nandscript-watch-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.nand"), "A\nINPUTS: a\nout = NAND(a, a)\n").unwrap();
+        fs::write(dir.join("b.nand"), "B\nINPUTS: a\nout = A(a)\n").unwrap();
+
+        // Watched through a path that only matches notify's once canonicalized
+        let mut cpu = ChipEvaluator::new();
+        let mut watcher = cpu.watch(dir.join("..").join(dir.file_name().unwrap())).unwrap();
+        let sender = simulate(&mut watcher);
+        let a = dir.canonicalize().unwrap().join("a.nand");
+        let b = dir.canonicalize().unwrap().join("b.nand");
+
+        fs::write(&b, "C\nINPUTS: a\nout = A(a)\n").unwrap();
+        sender.send(Ok(Event::new(EventKind::Any).add_path(b.clone()))).unwrap();
+        assert_eq!(cpu.reload_changes(&mut watcher).unwrap(), vec![String::from("C")]);
+        assert_eq!(cpu.chip_names(), ["A", "C"]);
+
+        // A chip already defined by another file is rejected like load_dir does, leaving the chips as they were
+        fs::write(&b, "A\nINPUTS: a\nout = a\n").unwrap();
+        sender.send(Ok(Event::new(EventKind::Any).add_path(b.clone()))).unwrap();
+        assert_eq!(cpu.reload_changes(&mut watcher).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(cpu.chip_names(), ["A", "C"]);
+
+        fs::remove_file(&a).unwrap();
+        sender.send(Ok(Event::new(EventKind::Any).add_path(a).add_path(b))).unwrap();
+        assert_eq!(cpu.reload_changes(&mut watcher).unwrap(), vec![String::from("A")]);
+        assert_eq!(cpu.chip_names(), ["A"]);
+        assert_eq!(cpu.inputs_of("A"), vec![String::from("a")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use crate::{ChipEvaluator, Executable, Token, lex_literal, parse};
+
+// A string as a quoted JSON string, escaping quotes, backslashes and control characters
+pub fn json_string(value: &str) -> String {
+    let mut result = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => result += "\\\"",
+            '\\' => result += "\\\\",
+            c if (c as u32) < 0x20 => result += &format!("\\u{:04x}", c as u32),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+// A JSON string at the start of `text`, and what follows it
+fn read_json_string(text: &str) -> Result<(String, &str), String> {
+    let mut chars = text.strip_prefix('"').ok_or_else(|| format!("Expected a string at {}", text))?.char_indices();
+    let mut result = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((result, &text[i + 2..])),
+            '\\' => {
+                let escaped = match chars.next().map(|(_, c)| c) {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('b') => '\u{8}',
+                    Some('f') => '\u{c}',
+                    Some('u') => {
+                        let hex: String = (0..4).filter_map(|_| chars.next().map(|(_, c)| c)).collect();
+                        u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32).ok_or_else(|| format!("Invalid escape \\u{}", hex))?
+                    },
+                    Some(c) => c,
+                    None => break,
+                };
+                result.push(escaped);
+            },
+            c => result.push(c),
+        }
+    }
+    Err(String::from("Unterminated string"))
+}
+
+// An object such as `{"a": 1, "b": 0x0f, "c": "0b1010"}`. Numbers, true, false and strings are all
+// read as NandScript literals.
+fn read_inputs(cpu: &ChipEvaluator, json: &str) -> Result<HashMap<String, u8>, String> {
+    let mut inputs = HashMap::<String, u8>::new();
+    let mut rest = json.trim().strip_prefix('{').ok_or("Expected a JSON object of inputs")?.trim_start();
+    if let Some(after) = rest.strip_prefix('}') {
+        rest = after;
+    }
+    else {
+        loop {
+            let (name, after) = read_json_string(rest)?;
+            rest = after.trim_start().strip_prefix(':').ok_or_else(|| format!("Expected ':' after {}", json_string(&name)))?.trim_start();
+            let value = if rest.starts_with('"') {
+                let (value, after) = read_json_string(rest)?;
+                rest = after;
+                value
+            }
+            else {
+                let end = rest.find([',', '}']).unwrap_or(rest.len());
+                let value = rest[..end].trim().to_string();
+                rest = &rest[end..];
+                value
+            };
+            match lex_literal(&value).and_then(|literal| cpu.literal(&literal).ok().flatten()) {
+                Some(value) => inputs.insert(name, value),
+                None => return Err(format!("Invalid value for {}: {}", name, value)),
+            };
+
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after.trim_start();
+                continue;
+            }
+            rest = rest.strip_prefix('}').ok_or("Expected ',' or '}' between inputs")?;
+            break;
+        }
+    }
+    if !rest.trim().is_empty() {
+        return Err(String::from("Unexpected text after the inputs object"));
+    }
+    Ok(inputs)
+}
+
+fn evaluate_program(source: &str, inputs_json: &str) -> Result<(String, u8), String> {
+    let cpu = ChipEvaluator::with_stdlib();
+    let inputs = read_inputs(&cpu, inputs_json)?;
+    let code = parse(source).map_err(|e| e.to_string())?;
+    // The last statement, which may assign a name an earlier statement already did
+    let output = code.iter().rev().find_map(|tok| match tok {
+        Token::Output(name) => Some(name.clone()),
+        _ => None,
+    }).ok_or("The program has no statements")?;
+    let value = cpu.eval(code, &inputs).map_err(|e| e.to_string())?.get(&output).copied().unwrap_or(0) & cpu.mask();
+    Ok((output, value))
+}
+
+// Evaluates a program against the standard library, for embedding where there are no files or
+// threads, such as a wasm32-unknown-unknown build. Inputs come as a JSON object, the result is
+// `{"output": "out", "value": 170}` for the last statement or `{"error": "..."}`.
+pub fn evaluate(source: &str, inputs_json: &str) -> String {
+    match evaluate_program(source, inputs_json) {
+        Ok((output, value)) => format!("{{\"output\": {}, \"value\": {}}}", json_string(&output), value),
+        Err(e) => format!("{{\"error\": {}}}", json_string(&e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluate_reports_the_last_statement() {
+        assert_eq!(evaluate("x = 1\ny = 0\nx = NAND(x, y)\n", "{}"), "{\"output\": \"x\", \"value\": 255}");
+        assert_eq!(evaluate("out = NAND(a, b)", "{\"a\": 1, \"b\": \"0b1010\"}"), "{\"output\": \"out\", \"value\": 245}");
+    }
+
+    #[test]
+    fn json_strings_escape_quotes_and_control_characters() {
+        assert_eq!(json_string("a \"b\"\\\n"), "\"a \\\"b\\\"\\\\\\u000a\"");
+    }
+}
@@ -0,0 +1,115 @@
+use std::{fs, path::PathBuf, process::{Command, Output}};
+
+// A fresh directory holding the given files, unique to the test using it
+fn directory(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("nandscript-cli-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (file, contents) in files {
+        fs::write(dir.join(file), contents).unwrap();
+    }
+    dir
+}
+
+fn nandscript(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nandscript")).args(args).output().unwrap()
+}
+
+#[test]
+fn check_resolves_includes() {
+    let dir = directory("includes", &[
+        ("gates.nand", "NOT\nINPUTS: a\nout = NAND(a, a)\n"),
+        ("main.nand", "#include \"gates.nand\"\n\nBUF\nINPUTS: a\nout = NOT(NOT(a))\n"),
+    ]);
+    let output = nandscript(&["--check", dir.join("main.nand").to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // Including a file already in the directory does not define its chips twice
+    let output = nandscript(&["--check", dir.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn check_only_reads_nand_files_in_a_directory() {
+    let dir = directory("nand-files", &[
+        ("gates.nand", "NOT\nINPUTS: a\nout = NAND(a, a)\n"),
+        ("README.md", "# Gates\n\nNot NandScript.\n"),
+    ]);
+    let output = nandscript(&["--check", dir.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Checked 1 files"));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn json_format_prints_outputs_as_one_object() {
+    let dir = directory("json", &[
+        ("gates.nand", "NOT\nINPUTS: a\nout = NAND(a, a)\n"),
+        ("main.nand", "out = NOT(a)\nboth = NAND(a, b)\n"),
+    ]);
+    let output = nandscript(&["--format", "json", "--lib", dir.join("gates.nand").to_str().unwrap(), dir.join("main.nand").to_str().unwrap(), "a=0b1100", "b=0b1010"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "{\"out\": 243, \"both\": 247}\n");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn quiet_keeps_the_result_but_not_the_timings() {
+    let dir = directory("quiet", &[("main.nand", "out = NAND(a, b)\n")]);
+    let program = dir.join("main.nand");
+    let output = nandscript(&[program.to_str().unwrap(), "a=1", "b=0"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Evaluated in"));
+    let output = nandscript(&["--quiet", program.to_str().unwrap(), "a=1", "b=0"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "out: 0b11111111\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn dump_ast_prints_the_token_tree() {
+    let dir = directory("dump-ast", &[("main.nand", "out = NAND(a, NOT.out(b: 1))\n")]);
+    let output = nandscript(&["--quiet", "--dump-ast", dir.join("main.nand").to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let dump = String::from_utf8_lossy(&output.stdout);
+    for variant in ["Output(", "Expression(", "Chip(", "Input(", "ChipIO(", "IO("] {
+        assert!(dump.contains(variant), "{} missing from {}", variant, dump);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn check_fails_on_a_broken_chip() {
+    let dir = directory("broken", &[
+        ("gates.nand", "NOT\nINPUTS: a\nout = NAND(a, a)\n"),
+        ("broken.nand", "T\nINPUTS: a\nout = NAND(NOT(a))\n"),
+    ]);
+    let output = nandscript(&["--check", dir.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Error: T: NAND takes 2 inputs but 1 are given"), "{}", stderr);
+    assert!(stderr.contains("Checked 2 files: 1 errors, 0 warnings"), "{}", stderr);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn eval_picks_a_named_output() {
+    let output = nandscript(&["--quiet", "--eval", "NAND.out(a:1,b:1)"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "out: 0b00000000\n");
+    let output = nandscript(&["--quiet", "--format", "decimal", "--eval", "NAND.out(a: 1, b: x)", "x=0b1010"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "out: 245\n");
+}
+
+#[test]
+fn json_output_is_the_same_every_run() {
+    let dir = directory("json-stable", &[("main.nand", "z = NAND(a, b)\ny = NAND(z, a)\nx = NAND(z, b)\nw = NAND(x, y)\nv = NAND(w, w)\n")]);
+    let run = || nandscript(&["--format", "json", dir.join("main.nand").to_str().unwrap(), "a=0b1100", "b=0b1010"]).stdout;
+    let first = run();
+    assert_eq!(String::from_utf8_lossy(&first), "{\"z\": 247, \"y\": 251, \"x\": 253, \"w\": 6, \"v\": 249}\n");
+    for _ in 0..4 {
+        assert_eq!(run(), first);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}